@@ -1,120 +1,957 @@
 use bevy::{
-    prelude::{Commands, EventReader, Plugin, Res, ResMut, SystemStage, Time, Timer, World},
+    ecs::system::SystemParam,
+    prelude::{
+        default, Assets, Camera, Camera2d, Camera2dBundle, ClearColorConfig, Commands, CoreStage,
+        Entity, EventReader, EventWriter, Handle, Image, ParallelSystemDescriptorCoercion, Plugin,
+        Query, Res, ResMut, Sprite, SpriteBundle, SystemStage, Time, Timer, Transform, UVec2,
+        UiCameraConfig, Vec2, World,
+    },
     render::{
+        camera::{RenderTarget, Viewport},
+        main_graph::node::CAMERA_DRIVER,
+        render_asset::{PrepareAssetLabel, RenderAssets},
         render_graph::{self, Node, RenderGraph},
         render_resource::{
             Buffer, BufferDescriptor, BufferUsages, Extent3d, ImageCopyBuffer, ImageCopyTexture,
-            ImageDataLayout, MapMode, Origin3d, TextureAspect,
+            ImageDataLayout, MapMode, Origin3d, TextureAspect, TextureDescriptor, TextureDimension,
+            TextureFormat, TextureUsages,
         },
         renderer::{RenderContext, RenderDevice},
-        view::WindowSurfaces,
+        texture::BevyDefault,
+        view::RenderLayers,
         RenderApp, RenderStage,
     },
-    window::{Window, Windows},
+    tasks::AsyncComputeTaskPool,
+    window::{WindowId, Windows},
+};
+use gif::{Encoder, EncodingError, Frame, Repeat};
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Seek, SeekFrom},
+    mem,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use gif::Repeat;
-use std::{mem, num::NonZeroU32, path::Path, time::Duration};
+use wgpu::{BufferAsyncError, Maintain};
 
 #[derive(Clone)]
 pub struct GifCaptureSettings {
     pub duration: f32,
-    pub path: &'static str,
+    /// Captures exactly this many frames instead of running for `duration`, no matter how long they take to render.
+    /// None of these frames are skipped, so the render thread waits on the GPU and the encoder when they fall behind,
+    /// and frames rendered less than 20ms apart make the gif play back slower than the game ran.
+    /// Frames are counted once they're read back from the GPU, so frames that couldn't be copied don't count.
+    /// When no frame makes it back for `duration` seconds, the capture fails rather than waiting forever.
+    /// Ignored by replays.
+    pub frames: Option<u32>,
+    /// Where the gif is saved, relative to `directory`. Can contain these tokens, which are filled in when a capture starts,
+    /// and again for every gif a replay saves:
+    /// - `{date}` and `{time}`: the UTC date and time, like `2026-10-18` and `14-03-11`.
+    /// - `{counter}`: the number of captures started since the app started, like `0003`.
+    /// - `{session}`: the `CaptureId` of the capture.
+    /// - `{window}`: the captured window, `primary` for the primary window. `camera<entity>` or `image` for other sources.
+    ///
+    /// Tokens can only be used in the file name, since the directory a gif is saved in has to exist already.
+    pub path: PathBuf,
+    /// The directory gifs are saved in. Empty for the working directory.
+    pub directory: PathBuf,
+    /// What to do when there already is a file at the path a gif is saved to.
+    pub overwrite: OverwritePolicy,
+    /// The order the captured frames are played back in.
+    pub playback: Playback,
     pub repeat: Repeat,
     pub speed: i32,
+    /// What to do with frames captured after the window was resized.
+    pub resize_policy: ResizePolicy,
+    /// The resolution the gif is saved at. Capturing itself always happens at the physical size of the window.
+    pub resolution: OutputResolution,
+    /// Whether to capture a single clip, or to keep a rolling replay of the last few seconds.
+    pub mode: CaptureMode,
+    /// How game time relates to real time while capturing.
+    pub timing: CaptureTiming,
+    /// What gets captured. Changing it only affects captures started afterwards.
+    pub source: CaptureSource,
+    /// The part of the captured source that ends up in the gif. `None` captures all of it.
+    pub region: Option<CaptureRegion>,
+    /// The most frames captured per second. `None` captures every rendered frame.
+    /// Ignored in `CaptureMode::TimeLapse`, which picks its frames by its own interval.
+    pub target_fps: Option<f32>,
     _private: (),
 }
 
+impl GifCaptureSettings {
+    /// Creates a new GifCaptureSettings. Returns an error for bad options passed.
+    pub fn new(
+        duration: f32,
+        path: impl Into<PathBuf>,
+        repeat: Repeat,
+        speed: i32,
+    ) -> Result<GifCaptureSettings, GifCaptureError> {
+        let settings = GifCaptureSettings {
+            duration,
+            path: path.into(),
+            repeat,
+            speed,
+            ..default()
+        };
+        settings.validate()?;
+        check_output_path(&settings.directory.join(&settings.path))?;
+        Ok(settings)
+    }
+
+    /// Checks the options that can also be overridden when a capture is started.
+    /// The output path is checked separately, once its tokens are filled in.
+    fn validate(&self) -> Result<(), GifCaptureError> {
+        let directory = self.path.parent().unwrap_or_else(|| Path::new(""));
+        if PATH_TOKENS
+            .iter()
+            .any(|token| directory.to_string_lossy().contains(token))
+        {
+            return Err(GifCaptureError::TokenInDirectory(self.path.clone()));
+        }
+        if !(1..=30).contains(&self.speed) {
+            return Err(GifCaptureError::InvalidSpeed(self.speed));
+        }
+        if !(self.duration.is_finite() && self.duration > 0.0) {
+            return Err(GifCaptureError::InvalidDuration(self.duration));
+        }
+        if let OutputResolution::ScaleFactor(factor) = self.resolution {
+            if !(factor.is_finite() && factor > 0.0) {
+                return Err(GifCaptureError::InvalidScaleFactor(factor));
+            }
+        }
+        if let Some(fps) = self.target_fps {
+            if !(fps.is_finite() && fps > 0.0) {
+                return Err(GifCaptureError::InvalidFps(fps));
+            }
+        }
+        if self.frames == Some(0) {
+            return Err(GifCaptureError::NoFrames);
+        }
+        if let Some(region) = self.region {
+            if region.width == 0 || region.height == 0 {
+                return Err(GifCaptureError::InvalidRegion(region));
+            }
+        }
+        match self.timing {
+            CaptureTiming::Movie { fps } if !(fps.is_finite() && fps > 0.0) => {
+                return Err(GifCaptureError::InvalidFps(fps));
+            }
+            CaptureTiming::SlowMotion { scale } if !(scale.is_finite() && scale > 0.0) => {
+                return Err(GifCaptureError::InvalidTimeScale(scale));
+            }
+            _ => {}
+        }
+        if let CaptureMode::Replay { length } = self.mode {
+            if !(length.is_finite() && length > 0.0) {
+                return Err(GifCaptureError::InvalidReplayLength(length));
+            }
+        }
+        if let CaptureMode::TimeLapse { every, fps } = self.mode {
+            let valid = match every {
+                TimeLapseInterval::Seconds(seconds) => seconds.is_finite() && seconds > 0.0,
+                TimeLapseInterval::Frames(frames) => frames > 0,
+            };
+            if !valid {
+                return Err(GifCaptureError::InvalidInterval(every));
+            }
+            if !(fps.is_finite() && fps > 0.0) {
+                return Err(GifCaptureError::InvalidFps(fps));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether none of the frames can be skipped, even when the GPU or the encoder fall behind.
+    fn keeps_every_frame(&self) -> bool {
+        self.frames.is_some()
+            || matches!(self.timing, CaptureTiming::Movie { .. })
+            || matches!(self.mode, CaptureMode::TimeLapse { .. })
+    }
+
+    /// Gets the capture time between two captured frames, if frames are picked on a fixed grid of capture time.
+    fn frame_interval(&self) -> Option<f64> {
+        match self.mode {
+            CaptureMode::TimeLapse {
+                every: TimeLapseInterval::Seconds(seconds),
+                ..
+            } => Some(seconds as f64),
+            CaptureMode::TimeLapse { .. } => None,
+            _ => self.target_fps.map(|fps| 1.0 / fps as f64),
+        }
+    }
+
+    /// Gets the size of the gif for a source of the given physical size, or `None` if the region doesn't overlap it.
+    fn output_size(
+        &self,
+        physical_width: u32,
+        physical_height: u32,
+        scale_factor: f64,
+    ) -> Option<(u32, u32)> {
+        let (width, height) = match self.region {
+            Some(region) => {
+                let region = region.clip(physical_width, physical_height)?;
+                (region.width, region.height)
+            }
+            None => (physical_width, physical_height),
+        };
+        Some(self.resolution.output_size(width, height, scale_factor))
+    }
+}
+
+/// The tokens that can be used in the file name of `GifCaptureSettings::path`.
+const PATH_TOKENS: [&str; 5] = ["{date}", "{time}", "{counter}", "{session}", "{window}"];
+
+/// The largest width and height a gif can have.
+const MAX_GIF_SIZE: u32 = u16::MAX as u32;
+
+/// Checks that the gif can be written to the path: the directory it goes in has to exist and be writable,
+/// and the file has to have the `gif` extension.
+fn check_output_path(path: &Path) -> Result<(), GifCaptureError> {
+    let is_gif = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("gif"));
+    if !is_gif {
+        return Err(GifCaptureError::WrongExtension(path.to_path_buf()));
+    }
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match fs::metadata(directory) {
+        Ok(metadata) if metadata.is_dir() => {
+            // The permissions alone can't tell whether this process may write there, so it's tried out.
+            if probe_directory(directory) {
+                Ok(())
+            } else {
+                Err(GifCaptureError::DirectoryNotWritable(
+                    directory.to_path_buf(),
+                ))
+            }
+        }
+        _ => Err(GifCaptureError::DirectoryNotFound(directory.to_path_buf())),
+    }
+}
+
+/// Creates and removes a file in the directory, to find out whether it can be written to.
+/// Files that are already there are never touched, a probe with the same name is tried under the next name instead.
+fn probe_directory(directory: &Path) -> bool {
+    static NEXT_PROBE: AtomicU64 = AtomicU64::new(0);
+    for _ in 0..16 {
+        let probe = directory.join(format!(
+            ".gif_capture_probe_{}_{}",
+            process::id(),
+            NEXT_PROBE.fetch_add(1, Ordering::Relaxed)
+        ));
+        match OpenOptions::new().write(true).create_new(true).open(&probe) {
+            Ok(_) => {
+                let _ = fs::remove_file(&probe);
+                return true;
+            }
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+/// Checks that a gif of the given size can be encoded.
+fn check_size(width: u32, height: u32) -> Result<(), GifCaptureError> {
+    if width > MAX_GIF_SIZE || height > MAX_GIF_SIZE {
+        return Err(GifCaptureError::TooLarge { width, height });
+    }
+    Ok(())
+}
+
+/// A rectangle of the captured source, in physical pixels from its top left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CaptureRegion {
+    /// Clips the region to a source of the given size. `None` if none of the region is left.
+    fn clip(&self, width: u32, height: u32) -> Option<CaptureRegion> {
+        let right = self.x.saturating_add(self.width).min(width);
+        let bottom = self.y.saturating_add(self.height).min(height);
+        if self.x >= right || self.y >= bottom {
+            return None;
+        }
+        Some(CaptureRegion {
+            x: self.x,
+            y: self.y,
+            width: right - self.x,
+            height: bottom - self.y,
+        })
+    }
+
+    /// Places the region, given from the top left corner of `outer`, inside of it. `None` if none of the region is left.
+    fn within(&self, outer: CaptureRegion) -> Option<CaptureRegion> {
+        let region = self.clip(outer.width, outer.height)?;
+        Some(CaptureRegion {
+            x: outer.x + region.x,
+            y: outer.y + region.y,
+            ..region
+        })
+    }
+}
+
+/// The order the frames of a gif are played back in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Playback {
+    /// In the order they were captured.
+    Forward,
+    /// From the last captured frame to the first.
+    /// Every frame is held in memory until the gif is finished, at a byte per pixel, so about 2MB per frame of a 1080p gif.
+    Reverse,
+    /// Forward, then back to the start, without showing the first and last frame twice. Loops seamlessly.
+    /// Holds every frame in memory until the gif is finished, just like `Reverse`.
+    Boomerang,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Playback::Forward
+    }
+}
+
+/// Decides what happens to a file that's already at the path a gif is saved to.
+/// Gifs are written to a temporary file first, and only moved to their path once they're complete,
+/// so a failed or cancelled capture never touches an existing file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Replaces the existing file.
+    Overwrite,
+    /// Fails the capture, keeping the existing file.
+    Fail,
+    /// Saves the gif next to the existing file, with the first free `_1`, `_2`, ... suffix.
+    AutoSuffix,
+}
+
+impl Default for OverwritePolicy {
+    fn default() -> Self {
+        OverwritePolicy::AutoSuffix
+    }
+}
+
+impl OverwritePolicy {
+    /// Moves a finished gif from its temporary file to the path it's meant for, or next to it, and gets where it ended up.
+    /// Unless overwriting, the gif is linked into place rather than renamed, which fails instead of replacing a file
+    /// that showed up since it was checked for, like the gif of another save of the same replay.
+    fn persist(&self, temp_path: &Path, path: &Path) -> Result<PathBuf, GifCaptureError> {
+        if *self == OverwritePolicy::Overwrite {
+            fs::rename(temp_path, path)?;
+            return Ok(path.to_path_buf());
+        }
+        for index in 0.. {
+            let target = match index {
+                0 => path.to_path_buf(),
+                index => suffixed_path(path, &index.to_string()),
+            };
+            match fs::hard_link(temp_path, &target) {
+                Ok(()) => {
+                    // The gif is in place already, the temporary file is only a second name for it.
+                    let _ = fs::remove_file(temp_path);
+                    return Ok(target);
+                }
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    if *self == OverwritePolicy::Fail {
+                        return Err(GifCaptureError::AlreadyExists(target));
+                    }
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+        unreachable!("every suffix is taken")
+    }
+}
+
+/// What a capture records.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CaptureSource {
+    /// Everything rendered to the window.
+    Window(WindowId),
+    /// Only what the camera renders, at the size of its viewport if it has one, or of its render target otherwise.
+    Camera(Entity),
+    /// An image some camera renders into, at the size of the image. It needs an 8-bit RGBA or BGRA format.
+    Image(Handle<Image>),
+}
+
+impl Default for CaptureSource {
+    fn default() -> Self {
+        CaptureSource::Window(WindowId::primary())
+    }
+}
+
+/// How game time relates to real time while capturing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaptureTiming {
+    /// The game runs as usual.
+    RealTime,
+    /// Every frame advances `Time` by exactly `1 / fps` seconds, no matter how long it actually took.
+    /// The game can run slower than real time, but the gif plays back perfectly smooth, and the same every time.
+    /// While such a capture runs, the whole app sees the fixed timestep, including other captures.
+    /// Frame rates above 50 play back slower, since gif frames can't be shown shorter than 20ms.
+    Movie { fps: f32 },
+    /// Every frame advances `Time` by `scale` times the real time it took, so `0.25` runs the game at a quarter of its speed.
+    /// Frames are shown in the gif for as long as they took in real time, so the gif plays back slowed down as well.
+    /// `duration` counts game time: a 2 second capture at `0.25` takes 8 seconds, and makes an 8 second gif.
+    /// While such a capture runs, the whole app sees the scaled time, including other captures.
+    SlowMotion { scale: f32 },
+}
+
+impl Default for CaptureTiming {
+    fn default() -> Self {
+        CaptureTiming::RealTime
+    }
+}
+
+/// How a started capture decides which frames end up in the gif.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaptureMode {
+    /// Captures for `duration` seconds after the start event, then saves the gif.
+    Clip,
+    /// Keeps capturing until stopped, while only holding on to the last `length` seconds of frames.
+    /// Those frames are saved whenever a `GifCaptureSaveReplayEvent` is sent, and once more when the capture is stopped.
+    Replay { length: f32 },
+    /// Captures for `duration` seconds like a clip, but only keeps one frame `every` interval,
+    /// and plays the kept frames back at `fps` frames per second.
+    /// Turns a long session into a short gif: an hour with a frame every 6 seconds plays back in 24 seconds at 25 fps.
+    TimeLapse { every: TimeLapseInterval, fps: f32 },
+}
+
+impl Default for CaptureMode {
+    fn default() -> Self {
+        CaptureMode::Clip
+    }
+}
+
+/// How often a capture in `CaptureMode::TimeLapse` keeps a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeLapseInterval {
+    /// One frame every given number of seconds of capture time.
+    Seconds(f32),
+    /// One frame out of every given number of rendered frames.
+    Frames(u32),
+}
+
+/// The resolution of the saved gif, relative to the physical size of the captured window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputResolution {
+    /// One gif pixel per physical pixel of the window.
+    Physical,
+    /// One gif pixel per logical pixel of the window, so a window with a scale factor of 2.0 produces a gif half its physical size.
+    Logical,
+    /// The physical size of the window multiplied by the given factor.
+    ScaleFactor(f64),
+}
+
+impl Default for OutputResolution {
+    fn default() -> Self {
+        OutputResolution::Physical
+    }
+}
+
+impl OutputResolution {
+    /// Gets the size of the gif for a window of the given physical size and scale factor.
+    fn output_size(
+        &self,
+        physical_width: u32,
+        physical_height: u32,
+        scale_factor: f64,
+    ) -> (u32, u32) {
+        let factor = match self {
+            OutputResolution::Physical => return (physical_width, physical_height),
+            OutputResolution::Logical => 1.0 / scale_factor,
+            OutputResolution::ScaleFactor(factor) => *factor,
+        };
+        (
+            ((physical_width as f64 * factor).round() as u32).max(1),
+            ((physical_height as f64 * factor).round() as u32).max(1),
+        )
+    }
+}
+
+/// Decides how frames of a different size than the first one are handled when the window is resized mid-capture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResizePolicy {
+    /// Keeps the size the window had when the capture started, and fits later frames inside of it
+    /// while keeping their aspect ratio. The leftover space is filled with black bars.
+    Letterbox,
+    /// Keeps the size the window had when the capture started, and stretches later frames to fill it.
+    Scale,
+    /// Finishes the current gif whenever the window size changes and starts a new one at the new size.
+    /// The first gif is saved to the settings path, later ones get a `_1`, `_2`, ... suffix.
+    SplitClip,
+}
+
+impl Default for ResizePolicy {
+    fn default() -> Self {
+        ResizePolicy::Letterbox
+    }
+}
+
 impl Default for GifCaptureSettings {
     fn default() -> Self {
         GifCaptureSettings {
             duration: 5.0,
-            path: "",
+            frames: None,
+            path: PathBuf::from("{date}_{time}_{counter}.gif"),
+            directory: PathBuf::new(),
+            overwrite: OverwritePolicy::default(),
+            playback: Playback::default(),
             repeat: Repeat::Infinite,
             speed: 10,
+            resize_policy: ResizePolicy::default(),
+            resolution: OutputResolution::default(),
+            mode: CaptureMode::default(),
+            timing: CaptureTiming::default(),
+            source: CaptureSource::default(),
+            region: None,
+            target_fps: None,
             _private: (),
         }
     }
 }
 
-pub struct GifCaptureSettingsError {
-    pub reason: String,
+/// Everything that can go wrong while setting up, capturing or saving a gif.
+#[derive(Debug)]
+pub enum GifCaptureError {
+    /// The directory the gif would be saved in doesn't exist.
+    DirectoryNotFound(PathBuf),
+    /// The directory the gif would be saved in can't be written to.
+    DirectoryNotWritable(PathBuf),
+    /// The output path doesn't have the `gif` extension.
+    WrongExtension(PathBuf),
+    /// The path given to the settings has a token in one of its directories, rather than in its file name.
+    TokenInDirectory(PathBuf),
+    /// There already is a file at the output path, and the overwrite policy is `OverwritePolicy::Fail`.
+    AlreadyExists(PathBuf),
+    /// The speed given to the settings is outside of the 1 to 30 range, see: https://docs.rs/gif/0.11.3/gif/struct.Frame.html#method.from_rgba_speed
+    InvalidSpeed(i32),
+    /// The duration given to the settings isn't a positive number of seconds.
+    InvalidDuration(f32),
+    /// The target frame rate given to the settings isn't a positive number.
+    InvalidFps(f32),
+    /// The factor of `OutputResolution::ScaleFactor` isn't a positive number.
+    InvalidScaleFactor(f64),
+    /// The settings ask for a capture of zero frames, or the capture ended or a replay was saved before any frame was captured.
+    NoFrames,
+    /// The time scale given to the settings for slow motion isn't a positive number.
+    InvalidTimeScale(f32),
+    /// The time-lapse interval given to the settings isn't a positive number of seconds or frames.
+    InvalidInterval(TimeLapseInterval),
+    /// A `GifCaptureSaveReplayEvent` was sent for a capture that isn't in `CaptureMode::Replay`.
+    NotAReplay,
+    /// The length of `CaptureMode::Replay` isn't a positive number of seconds.
+    InvalidReplayLength(f32),
+    /// The region given to the settings is empty, or doesn't overlap the captured source.
+    InvalidRegion(CaptureRegion),
+    /// No frame of a capture of a number of frames could be read back for the given `duration` in seconds, so it was given up on.
+    Stalled(f32),
+    /// The gif would be larger than the 65535 by 65535 pixels a gif can hold.
+    TooLarge { width: u32, height: u32 },
+    /// The window being captured doesn't exist.
+    WindowNotFound,
+    /// The source, or a window or camera it overlaps with, is already being captured by the capture with the given id.
+    AlreadyCapturing(CaptureId),
+    /// The camera being captured doesn't exist, or has no `Camera` component.
+    CameraNotFound(Entity),
+    /// The image being captured doesn't exist.
+    ImageNotFound,
+    /// The image being captured isn't 8-bit RGBA or BGRA, the only formats that can be encoded.
+    UnsupportedFormat(TextureFormat),
+    /// Reading a frame back from the GPU failed.
+    BufferMap(BufferAsyncError),
+    /// Creating or writing the gif file failed, or the encoder thread couldn't be started.
+    Io(io::Error),
+    /// The gif encoder rejected a frame.
+    Encoding(EncodingError),
 }
 
-impl GifCaptureSettings {
-    /// Creates a new GifCaptureSettings. Returns an error for bad options passed.
-    pub fn new(
-        duration: f32,
-        path: &'static str,
-        repeat: Repeat,
-        speed: i32,
-    ) -> Result<GifCaptureSettings, GifCaptureSettingsError> {
-        if !Path::exists(Path::new(path)) {
-            return Err(GifCaptureSettingsError {
-                reason: format!("Path: {} doesn't exist.", path),
-            });
+impl fmt::Display for GifCaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GifCaptureError::DirectoryNotFound(path) => {
+                write!(f, "Directory: {} doesn't exist.", path.display())
+            }
+            GifCaptureError::DirectoryNotWritable(path) => {
+                write!(f, "Directory: {} isn't writable.", path.display())
+            }
+            GifCaptureError::WrongExtension(path) => {
+                write!(f, "Path: {} must have the gif extension.", path.display())
+            }
+            GifCaptureError::TokenInDirectory(path) => write!(
+                f,
+                "Path: {} can only have tokens in its file name.",
+                path.display()
+            ),
+            GifCaptureError::AlreadyExists(path) => {
+                write!(f, "Path: {} already exists.", path.display())
+            }
+            GifCaptureError::InvalidSpeed(speed) => {
+                write!(f, "Speed: {} must be within range of 1 to 30.", speed)
+            }
+            GifCaptureError::InvalidDuration(duration) => {
+                write!(
+                    f,
+                    "Duration: {} must be a positive number of seconds.",
+                    duration
+                )
+            }
+            GifCaptureError::InvalidFps(fps) => {
+                write!(f, "Target fps: {} must be a positive number.", fps)
+            }
+            GifCaptureError::InvalidScaleFactor(factor) => {
+                write!(f, "Scale factor: {} must be a positive number.", factor)
+            }
+            GifCaptureError::NoFrames => write!(f, "Frames: a capture needs at least 1 frame."),
+            GifCaptureError::InvalidTimeScale(scale) => {
+                write!(f, "Time scale: {} must be a positive number.", scale)
+            }
+            GifCaptureError::InvalidInterval(every) => write!(
+                f,
+                "Time-lapse interval: {:?} must be a positive number of seconds or frames.",
+                every
+            ),
+            GifCaptureError::NotAReplay => write!(f, "Only replays can be saved while running."),
+            GifCaptureError::InvalidReplayLength(length) => write!(
+                f,
+                "Replay length: {} must be a positive number of seconds.",
+                length
+            ),
+            GifCaptureError::InvalidRegion(region) => write!(
+                f,
+                "Region: {:?} must have a size, and overlap the captured source.",
+                region
+            ),
+            GifCaptureError::Stalled(duration) => {
+                write!(f, "No frame could be captured for {} seconds.", duration)
+            }
+            GifCaptureError::TooLarge { width, height } => write!(
+                f,
+                "The gif would be {}x{}, but gifs can't be larger than {}x{}.",
+                width, height, MAX_GIF_SIZE, MAX_GIF_SIZE
+            ),
+            GifCaptureError::WindowNotFound => write!(f, "The captured window doesn't exist."),
+            GifCaptureError::AlreadyCapturing(id) => {
+                write!(f, "The source is already being captured by {:?}.", id)
+            }
+            GifCaptureError::CameraNotFound(entity) => {
+                write!(f, "The captured camera {:?} doesn't exist.", entity)
+            }
+            GifCaptureError::ImageNotFound => write!(f, "The captured image doesn't exist."),
+            GifCaptureError::UnsupportedFormat(format) => write!(
+                f,
+                "The captured image has format {:?}, only 8-bit RGBA and BGRA images can be captured.",
+                format
+            ),
+            GifCaptureError::BufferMap(error) => {
+                write!(f, "Couldn't read the frame back from the GPU: {}", error)
+            }
+            GifCaptureError::Io(error) => write!(f, "Couldn't write the gif: {}", error),
+            GifCaptureError::Encoding(error) => write!(f, "Couldn't encode the gif: {}", error),
         }
-        if speed < 1 || speed > 30 {
-            return Err(GifCaptureSettingsError {
-                reason: format!("Speed: {} must be within range of 1 to 30, see: https://docs.rs/gif/0.11.3/gif/struct.Frame.html#method.from_rgba_speed", speed),
-            });
+    }
+}
+
+impl Error for GifCaptureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GifCaptureError::BufferMap(error) => Some(error),
+            GifCaptureError::Io(error) => Some(error),
+            GifCaptureError::Encoding(error) => Some(error),
+            _ => None,
         }
-        return Ok(GifCaptureSettings {
-            duration,
-            path,
-            repeat,
-            speed,
-            _private: (),
-        });
     }
 }
 
-/// Extracts the settings from the App world to the Render world.
-fn extract_settings(mut commands: Commands, settings: Option<Res<GifCaptureSettings>>) {
-    if let Some(settings) = settings {
-        commands.insert_resource(settings.clone());
+impl From<io::Error> for GifCaptureError {
+    fn from(error: io::Error) -> Self {
+        GifCaptureError::Io(error)
+    }
+}
+
+impl From<EncodingError> for GifCaptureError {
+    fn from(error: EncodingError) -> Self {
+        match error {
+            EncodingError::Io(error) => GifCaptureError::Io(error),
+            error => GifCaptureError::Encoding(error),
+        }
     }
 }
 
-fn extract_gif_capture(
+/// Extracts the captures from the App world to the Render world.
+fn extract_gif_captures(
     mut commands: Commands,
-    event: EventReader<GifCaptureStartEvent>,
-    mut gif_time: ResMut<GifTime>,
-    gif_settings: Res<GifCaptureSettings>,
+    captures: Res<GifCaptures>,
+    mut replay_events: EventReader<GifCaptureSaveReplayEvent>,
 ) {
-    gif_time
-        .timer
-        .set_duration(Duration::from_secs_f32(gif_settings.duration));
-    if !event.is_empty() {
-        commands.insert_resource(GifCaptureState::CurrentlyCapturing);
-        // Resets it, notably it resets it in the App world.
-        gif_time.timer.reset();
-    }
-    if gif_time.timer.just_finished() {
-        commands.insert_resource(GifCaptureState::JustFinishedCapturing);
-    }
+    let save_replay = replay_events
+        .iter()
+        .map(|event| event.id)
+        .collect::<Vec<_>>();
+    commands.insert_resource(ExtractedGifCaptures(
+        captures
+            .captures
+            .iter()
+            .map(|capture| {
+                let save_replay = save_replay.contains(&capture.id) && capture.state.is_active();
+                let saving = save_replay || capture.state == GifCaptureState::JustFinishedCapturing;
+                ExtractedGifCapture {
+                    id: capture.id,
+                    state: capture.state,
+                    settings: capture.settings.clone(),
+                    // Every gif of a replay gets the date and time it's saved at, so the ones of a long session can be told apart.
+                    path: match capture.settings.mode {
+                        CaptureMode::Replay { .. } if saving => output_path(
+                            &capture.settings,
+                            capture.id,
+                            capture.number,
+                            SystemTime::now(),
+                        ),
+                        _ => capture.path.clone(),
+                    },
+                    timestamp: capture.timestamp,
+                    elapsed: capture.time.elapsed,
+                    save_replay,
+                    capture_frame: capture.capture_frame,
+                    image: capture.target.image.clone(),
+                    scale_factor: capture.target.scale_factor,
+                    viewport: capture.target.viewport,
+                }
+            })
+            .collect(),
+    ));
 }
 
 #[derive(Default)]
 struct GifTime {
+    /// Counts game time, so a slow motion capture runs for `duration` seconds of what it captured.
     timer: Timer,
+    /// Seconds spent capturing since the start of the capture, not counting the time spent paused.
+    /// Game time, except for slow motion captures, which count real time so their frames get real-time delays.
+    elapsed: f64,
+}
+
+/// Every capture that is running, or that ended this frame.
+#[derive(Default)]
+struct GifCaptures {
+    captures: Vec<GifCapture>,
+    /// Captures started so far, for the `{counter}` path token.
+    counter: usize,
+}
+
+/// A single capture, along with the settings it was started with.
+struct GifCapture {
+    id: CaptureId,
+    state: GifCaptureState,
+    time: GifTime,
+    /// The settings when the capture started, with the options set on its start event.
+    /// Changing the settings only affects captures started afterwards.
+    settings: GifCaptureSettings,
+    /// Which capture this is since the app started, for the `{counter}` path token.
+    number: usize,
+    /// Where the gif is saved, with the tokens of the settings path filled in.
+    path: PathBuf,
+    target: GifCaptureTarget,
+    /// Whether this frame gets copied into the gif.
+    capture_frame: bool,
+    /// Capture time from which on the next frame is captured, when there is a target frame rate.
+    next_frame: f64,
+    /// Frames rendered while capturing, for time-lapses that keep one out of every few frames.
+    frames_rendered: u64,
+    /// Frames captured so far.
+    frames_picked: u64,
+    /// Where the frame captured this frame shows up in the gif, in seconds from its start.
+    /// The capture time, except for time-lapses, which space their frames out evenly.
+    timestamp: f64,
+}
+
+/// What the sources of new captures are looked up in.
+#[derive(SystemParam)]
+struct CaptureSources<'w, 's> {
+    windows: Res<'w, Windows>,
+    cameras: Query<'w, 's, &'static Camera>,
+}
+
+impl GifCaptures {
+    /// Starts capturing the source, unless it overlaps with a source that's already being captured by another capture.
+    /// Starting the same capture twice does nothing.
+    fn start(
+        &mut self,
+        id: CaptureId,
+        settings: GifCaptureSettings,
+        sources: &CaptureSources,
+    ) -> Result<(), GifCaptureError> {
+        if self.captures.iter().any(|capture| capture.id == id) {
+            return Ok(());
+        }
+        if let Some(capture) = self.captures.iter().find(|capture| {
+            capture.state.is_active()
+                && self.overlaps(&capture.settings.source, &settings.source, sources)
+        }) {
+            return Err(GifCaptureError::AlreadyCapturing(capture.id));
+        }
+        let slot = (0..)
+            .find(|slot| {
+                !self
+                    .captures
+                    .iter()
+                    .any(|capture| capture.target.slot == *slot)
+            })
+            .unwrap_or_default();
+        let number = self.counter + 1;
+        let path = output_path(&settings, id, number, SystemTime::now());
+        check_output_path(&path)?;
+        if settings.overwrite == OverwritePolicy::Fail && path.exists() {
+            return Err(GifCaptureError::AlreadyExists(path));
+        }
+        // Other sources are only checked once the gif is created, since their size isn't known until they're rendered.
+        if let CaptureSource::Window(window) = &settings.source {
+            if let Some(window) = sources.windows.get(*window) {
+                match settings.output_size(
+                    window.physical_width(),
+                    window.physical_height(),
+                    window.scale_factor(),
+                ) {
+                    Some((width, height)) => check_size(width, height)?,
+                    None => {
+                        if let Some(region) = settings.region {
+                            return Err(GifCaptureError::InvalidRegion(region));
+                        }
+                    }
+                }
+            }
+        }
+        self.counter = number;
+        self.captures.push(GifCapture {
+            id,
+            number,
+            state: GifCaptureState::CurrentlyCapturing,
+            time: GifTime {
+                timer: Timer::from_seconds(settings.duration, false),
+                elapsed: 0.0,
+            },
+            settings,
+            path,
+            target: GifCaptureTarget { slot, ..default() },
+            capture_frame: false,
+            next_frame: 0.0,
+            frames_rendered: 0,
+            frames_picked: 0,
+            timestamp: 0.0,
+        });
+        Ok(())
+    }
+
+    /// Whether two sources can't be captured at the same time. Capturing a window redirects every camera rendering to it,
+    /// so it can't be combined with capturing a single one of those cameras, which redirects that camera as well.
+    fn overlaps(&self, a: &CaptureSource, b: &CaptureSource, sources: &CaptureSources) -> bool {
+        if a == b {
+            return true;
+        }
+        let whole_window = |source: &CaptureSource| match source {
+            CaptureSource::Window(window) => Some(*window),
+            _ => None,
+        };
+        match (whole_window(a), whole_window(b)) {
+            (Some(window), None) => self.captured_window(b, sources) == Some(window),
+            (None, Some(window)) => self.captured_window(a, sources) == Some(window),
+            _ => false,
+        }
+    }
+
+    /// Gets the window the source is rendered to, if it's rendered to one.
+    fn captured_window(
+        &self,
+        source: &CaptureSource,
+        sources: &CaptureSources,
+    ) -> Option<WindowId> {
+        match source {
+            CaptureSource::Window(window) => Some(*window),
+            CaptureSource::Camera(entity) => {
+                // Cameras being captured already render into our image, but still belong to their window.
+                let redirected = self
+                    .captures
+                    .iter()
+                    .flat_map(|capture| &capture.target.cameras)
+                    .find(|(camera, _, _)| camera == entity)
+                    .map(|(_, window, _)| *window);
+                redirected.or_else(|| match &sources.cameras.get(*entity).ok()?.target {
+                    RenderTarget::Window(window) => Some(*window),
+                    RenderTarget::Image(_) => None,
+                })
+            }
+            CaptureSource::Image(_) => None,
+        }
+    }
+}
+
+/// Per-frame information about a capture, extracted into the Render world.
+struct ExtractedGifCapture {
+    id: CaptureId,
+    state: GifCaptureState,
+    settings: GifCaptureSettings,
+    path: PathBuf,
+    /// Where the frame being rendered shows up in the gif, in seconds from its start.
+    timestamp: f64,
+    /// Seconds spent capturing so far, not counting the time spent paused.
+    elapsed: f64,
+    /// Whether the replay should be saved this frame.
+    save_replay: bool,
+    /// Whether this frame gets copied into the gif.
+    capture_frame: bool,
+    /// The image the captured source is rendered into.
+    image: Option<Handle<Image>>,
+    /// Scale factor of the captured window, or 1.0 for images.
+    scale_factor: f64,
+    /// The part of the image the captured camera renders to, if it doesn't render to all of it.
+    viewport: Option<CaptureRegion>,
 }
 
+#[derive(Default)]
+struct ExtractedGifCaptures(Vec<ExtractedGifCapture>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum GifCaptureState {
-    Off,
     CurrentlyCapturing,
+    /// The capture is still running, but frames are skipped and the timer is stopped.
+    Paused,
+    /// Lasts for a single frame, during which the captured frames are saved.
     JustFinishedCapturing,
+    /// Lasts for a single frame, during which the captured frames are thrown away.
+    Cancelled,
 }
 
-impl Default for GifCaptureState {
-    fn default() -> Self {
-        GifCaptureState::Off
+impl GifCaptureState {
+    /// Whether a capture has been started and has not been finished or cancelled yet.
+    fn is_active(&self) -> bool {
+        matches!(
+            self,
+            GifCaptureState::CurrentlyCapturing | GifCaptureState::Paused
+        )
     }
 }
 
 struct DispatchGifCapture;
 
 /// Node for dispatching the gif capture in the RenderGraph.
-/// Copies the texture from the primary window surface, back to the buffer we created earlier.
+/// Copies the image each capture's source is rendered into, into the staging buffer picked for this frame.
 impl Node for DispatchGifCapture {
     fn run(
         &self,
@@ -122,194 +959,2501 @@ impl Node for DispatchGifCapture {
         render_context: &mut RenderContext,
         world: &World,
     ) -> Result<(), render_graph::NodeRunError> {
-        if let Some(gif_state) = world.get_resource::<GifCaptureState>() {
-            if let GifCaptureState::CurrentlyCapturing = gif_state {
-                let windows = world.get_resource::<Windows>().unwrap();
-                let window_surfaces = world.get_resource::<WindowSurfaces>().unwrap();
-                let command_encoder = world.get_resource::<RenderContext>().unwrap();
-                let output_buffer = world.get_resource::<GifBuffer>();
-                let primary_window = windows.primary();
-                let surface = window_surfaces.surfaces.get(&primary_window.id());
-                if let (Some(surface), Some(output_buffer)) = (surface, output_buffer) {
-                    let surface_texture = surface.get_current_texture().unwrap();
-                    let (_, padded_bytes_per_row, _) = get_buffer_size(&primary_window);
-                    render_context.command_encoder.copy_texture_to_buffer(
-                        ImageCopyTexture {
-                            texture: &surface_texture.texture,
-                            mip_level: 0,
-                            origin: Origin3d::ZERO,
-                            aspect: TextureAspect::All,
-                        },
-                        ImageCopyBuffer {
-                            buffer: &output_buffer.0,
-                            layout: ImageDataLayout {
-                                offset: 0,
-                                bytes_per_row: NonZeroU32::new(padded_bytes_per_row as u32),
-                                rows_per_image: NonZeroU32::new(primary_window.height() as u32),
-                            },
-                        },
-                        Extent3d {
-                            width: (primary_window.width() as u32),
-                            height: (primary_window.height() as u32),
-                            depth_or_array_layers: 0u32,
-                        },
-                    );
-                }
+        let (sessions, gpu_images) = match world
+            .get_resource::<GifCaptureSessions>()
+            .zip(world.get_resource::<RenderAssets<Image>>())
+        {
+            Some(resources) => resources,
+            None => return Ok(()),
+        };
+        for session in sessions.0.values() {
+            // Without a buffer there is nothing to copy into, because the frame is skipped.
+            let output_buffer = match session.pool.current_buffer() {
+                Some(output_buffer) => output_buffer,
+                None => continue,
+            };
+            let gpu_image = match session
+                .image
+                .as_ref()
+                .and_then(|image| gpu_images.get(image))
+            {
+                Some(gpu_image) => gpu_image,
+                None => continue,
+            };
+            // The image can get resized, which could have happened after the buffer was picked.
+            // The buffer is then left as it is, and handed back without being read.
+            if gpu_image.size != output_buffer.image_size {
+                continue;
             }
+            output_buffer.copied.store(true, Ordering::Relaxed);
+            let (_, padded_bytes_per_row, _) =
+                get_buffer_size(output_buffer.width, output_buffer.height);
+            render_context.command_encoder.copy_texture_to_buffer(
+                ImageCopyTexture {
+                    texture: &gpu_image.texture,
+                    mip_level: 0,
+                    origin: output_buffer.origin,
+                    aspect: TextureAspect::All,
+                },
+                ImageCopyBuffer {
+                    buffer: &output_buffer.buffer,
+                    layout: ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(padded_bytes_per_row as u32),
+                        rows_per_image: NonZeroU32::new(output_buffer.height),
+                    },
+                },
+                Extent3d {
+                    width: output_buffer.width,
+                    height: output_buffer.height,
+                    depth_or_array_layers: 1u32,
+                },
+            );
         }
         Ok(())
     }
 }
 
-pub struct GifCaptureStartEvent;
-pub struct GifCapturePlugin;
+/// Render layer only used to show the capture images on their windows.
+const PRESENTER_LAYER: u8 = RenderLayers::TOTAL_LAYERS as u8 - 1;
+
+/// Distance between the presenters of captures running at the same time. They share a render layer,
+/// so each presenter camera is placed far enough away to only see its own sprite.
+const PRESENTER_SPACING: f32 = 100_000.0;
 
+/// The image being copied into the gif, and what it takes to get the captured source rendered into it.
+/// Window surfaces can't be copied from in stock Bevy, so while capturing a window, its cameras render into an image of our own,
+/// which is shown on the window by a camera and sprite of our own. Images are copied from directly.
 #[derive(Default)]
-pub struct GifCaptureFrames(Vec<Vec<u8>>);
+struct GifCaptureTarget {
+    image: Option<Handle<Image>>,
+    scale_factor: f64,
+    /// The viewport of a captured camera rendering into an image, which only that part of the image is captured from.
+    viewport: Option<CaptureRegion>,
+    /// Cameras redirected to the image, along with the window they were rendering to and their viewport.
+    cameras: Vec<(Entity, WindowId, Option<Viewport>)>,
+    /// The camera and sprite showing the image on the window.
+    presenter: Vec<Entity>,
+    /// Where the presenter is placed, unique among the running captures.
+    slot: usize,
+}
 
-fn read_capture_events_and_tick_timer(mut gif_time: ResMut<GifTime>, time: Res<Time>) {
-    gif_time.timer.tick(time.delta());
-    if gif_time.timer.just_finished() {}
+/// Everything it takes to set up and tear down the images of the captured sources.
+#[derive(SystemParam)]
+struct CaptureTargetParams<'w, 's> {
+    commands: Commands<'w, 's>,
+    windows: Res<'w, Windows>,
+    images: ResMut<'w, Assets<Image>>,
+    cameras: Query<'w, 's, (Entity, &'static mut Camera)>,
+    sprites: Query<'w, 's, &'static mut Sprite>,
 }
 
-/// Core plugin for capturing gifs.
-impl Plugin for GifCapturePlugin {
-    fn build(&self, app: &mut bevy::prelude::App) {
-        app.init_resource::<GifTime>();
-        app.init_resource::<GifCaptureSettings>();
-        app.add_system(read_capture_events_and_tick_timer);
-        static GET_GIF_DATA: &str = "get_gif_data";
-        static GIF_CAPTURE: &str = "gif_capture";
-        let render_app = app.get_sub_app_mut(RenderApp).unwrap();
-        render_app
-            .init_resource::<GifCaptureFrames>()
-            .init_resource::<GifCaptureState>()
-            .add_system_to_stage(RenderStage::Extract, extract_settings)
-            .add_system_to_stage(RenderStage::Extract, extract_gif_capture)
-            .add_system_to_stage(RenderStage::Prepare, create_buffer)
-            .add_stage_before(
-                RenderStage::Cleanup,
-                GET_GIF_DATA,
-                SystemStage::single_threaded(),
-            )
-            .add_system_to_stage(GET_GIF_DATA, write_gif)
-            .add_system_to_stage(GET_GIF_DATA, save_gif_on_state);
+/// Sets up the image of each captured source while capturing, and tears it down once the capture is over.
+fn update_capture_targets(
+    mut params: CaptureTargetParams,
+    mut captures: ResMut<GifCaptures>,
+    outbox: Res<GifCaptureOutbox>,
+) {
+    for capture in captures.captures.iter_mut() {
+        let target = &mut capture.target;
+        if !capture.state.is_active() {
+            restore_capture_target(&mut params.commands, target, &mut params.cameras);
+            continue;
+        }
+        let result = match capture.settings.source.clone() {
+            CaptureSource::Window(window) => capture_window(&mut params, target, window, None),
+            CaptureSource::Camera(entity) => match params.cameras.get(entity) {
+                // Once redirected, the camera renders into our image, but is still captured through its window.
+                Ok((_, camera)) => match target
+                    .cameras
+                    .iter()
+                    .find(|(camera, _, _)| *camera == entity)
+                    .map_or(camera.target.clone(), |(_, window, _)| {
+                        RenderTarget::Window(*window)
+                    }) {
+                    RenderTarget::Window(window) => {
+                        capture_window(&mut params, target, window, Some(entity))
+                    }
+                    RenderTarget::Image(image) => {
+                        let viewport = camera.viewport.as_ref().map(|viewport| CaptureRegion {
+                            x: viewport.physical_position.x,
+                            y: viewport.physical_position.y,
+                            width: viewport.physical_size.x,
+                            height: viewport.physical_size.y,
+                        });
+                        capture_image(target, &mut params.images, image, viewport)
+                    }
+                },
+                Err(_) => Err(GifCaptureError::CameraNotFound(entity)),
+            },
+            CaptureSource::Image(image) => capture_image(target, &mut params.images, image, None),
+        };
+        if let Err(error) = result {
+            outbox.report_error(capture.id, error);
+            restore_capture_target(&mut params.commands, target, &mut params.cameras);
+        }
+    }
+}
 
-        let mut render_graph = render_app.world.get_resource_mut::<RenderGraph>().unwrap();
-        render_graph.add_node(GIF_CAPTURE, DispatchGifCapture {});
-        //render_graph.iter_nodes().for_each(|x| println!("{:?}", x));
-        render_graph.iter_sub_graphs().for_each(|x| {
-            println!("Graph name: {}", x.0);
-            x.1.iter_nodes().for_each(|y| println!("{:?}", y.name));
-        });
-        render_graph
-            .add_node_edge(bevy::core_pipeline::core_2d::graph::NAME, GIF_CAPTURE)
-            .unwrap();
-        render_graph
-            .add_node_edge(bevy::core_pipeline::core_3d::graph::NAME, GIF_CAPTURE)
-            .unwrap();
+/// Redirects the cameras of the window into an image of our own, and shows that image on the window instead.
+/// When `only_camera` is given, only that camera is redirected, and only its viewport is captured.
+fn capture_window(
+    params: &mut CaptureTargetParams,
+    target: &mut GifCaptureTarget,
+    window_id: WindowId,
+    only_camera: Option<Entity>,
+) -> Result<(), GifCaptureError> {
+    let CaptureTargetParams {
+        commands,
+        windows,
+        images,
+        cameras,
+        sprites,
+    } = params;
+    let window = windows
+        .get(window_id)
+        .ok_or(GifCaptureError::WindowNotFound)?;
+    let scale_factor = window.scale_factor();
+    // A single camera keeps its viewport on the presenter, and renders into the whole image instead.
+    let viewport = match only_camera {
+        Some(entity) => target
+            .cameras
+            .iter()
+            .find(|(camera, _, _)| *camera == entity)
+            .map(|(_, _, viewport)| viewport.clone())
+            .or_else(|| {
+                cameras
+                    .get(entity)
+                    .ok()
+                    .map(|(_, camera)| camera.viewport.clone())
+            })
+            .flatten(),
+        None => None,
+    };
+    let physical_size = match &viewport {
+        Some(viewport) => viewport.physical_size,
+        None => UVec2::new(window.physical_width(), window.physical_height()),
+    };
+    let size = Extent3d {
+        width: physical_size.x.max(1),
+        height: physical_size.y.max(1),
+        depth_or_array_layers: 1,
+    };
+    let image = match &target.image {
+        Some(image) => image.clone(),
+        None => {
+            let image = images.add(create_capture_image(size));
+            let priority = match only_camera.and_then(|entity| cameras.get(entity).ok()) {
+                // Keeping the place of the camera, so the cameras rendering after it still draw on top.
+                Some((_, camera)) => camera.priority,
+                // Rendering after every other camera.
+                None => isize::MAX,
+            };
+            let offset = target.slot as f32 * PRESENTER_SPACING;
+            let mut camera_bundle = Camera2dBundle {
+                camera: Camera {
+                    target: RenderTarget::Window(window_id),
+                    priority,
+                    viewport: viewport.clone(),
+                    ..default()
+                },
+                camera_2d: Camera2d {
+                    clear_color: ClearColorConfig::None,
+                },
+                ..default()
+            };
+            camera_bundle.transform.translation.x += offset;
+            let presenter_camera = commands
+                .spawn_bundle(camera_bundle)
+                .insert(UiCameraConfig { show_ui: false })
+                .insert(RenderLayers::layer(PRESENTER_LAYER))
+                .id();
+            let presenter_sprite = commands
+                .spawn_bundle(SpriteBundle {
+                    texture: image.clone(),
+                    transform: Transform::from_xyz(offset, 0.0, 0.0),
+                    ..default()
+                })
+                .insert(RenderLayers::layer(PRESENTER_LAYER))
+                .id();
+            target.image = Some(image.clone());
+            target.presenter = vec![presenter_camera, presenter_sprite];
+            image
+        }
+    };
+    target.scale_factor = scale_factor;
+    if let Some(image) = images.get_mut(&image) {
+        if image.texture_descriptor.size != size {
+            image.resize(size);
+        }
+    }
+    // The sprite covers the whole viewport of the presenter, which the 2d camera measures in logical pixels.
+    let logical_size = physical_size.as_vec2() / scale_factor as f32;
+    for &entity in &target.presenter {
+        if let Ok(mut sprite) = sprites.get_mut(entity) {
+            if sprite.custom_size != Some(logical_size) {
+                sprite.custom_size = Some(logical_size);
+            }
+        }
     }
+    // Done every frame, to also catch cameras spawned during the capture.
+    let window_target = RenderTarget::Window(window_id);
+    for (entity, mut camera) in cameras.iter_mut() {
+        let captured = match only_camera {
+            Some(only_camera) => entity == only_camera,
+            None => !target.presenter.contains(&entity),
+        };
+        if captured && camera.target == window_target {
+            camera.target = RenderTarget::Image(image.clone());
+            target
+                .cameras
+                .push((entity, window_id, camera.viewport.clone()));
+            if only_camera.is_some() {
+                camera.viewport = None;
+            }
+        }
+    }
+    Ok(())
 }
 
-struct GifBuffer(Buffer);
+/// Captures an image some camera renders into, or only the `viewport` of it when given.
+/// Copying from it needs `TextureUsages::COPY_SRC`, which is added if it's missing.
+fn capture_image(
+    target: &mut GifCaptureTarget,
+    images: &mut Assets<Image>,
+    handle: Handle<Image>,
+    viewport: Option<CaptureRegion>,
+) -> Result<(), GifCaptureError> {
+    let image = images.get(&handle).ok_or(GifCaptureError::ImageNotFound)?;
+    let format = image.texture_descriptor.format;
+    // Bgra is what cameras render into on most platforms, and gets converted when it's read back.
+    if !matches!(
+        format,
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+    ) && !is_bgra(format)
+    {
+        return Err(GifCaptureError::UnsupportedFormat(format));
+    }
+    // Only borrowing mutably when needed, since that uploads the image again.
+    if !image
+        .texture_descriptor
+        .usage
+        .contains(TextureUsages::COPY_SRC)
+    {
+        if let Some(image) = images.get_mut(&handle) {
+            image.texture_descriptor.usage |= TextureUsages::COPY_SRC;
+        }
+    }
+    target.image = Some(handle);
+    target.scale_factor = 1.0;
+    target.viewport = viewport;
+    Ok(())
+}
 
-/// Gets the buffer size needed to capture an entire window, where each pixel is a u32 color.
-/// Output: (unpadded_bytes_per_row, padded_bytes_per_row, total_buffer_size)
-fn get_buffer_size(window: &Window) -> (u32, usize, usize) {
-    let pixel_size = mem::size_of::<[u8; 4]>() as u32;
-    let unpadded_bytes_per_row = pixel_size * (window.width() as u32);
-    let padded_bytes_per_row =
-        RenderDevice::align_copy_bytes_per_row(unpadded_bytes_per_row as usize);
-    let buffer_size = padded_bytes_per_row * (window.height() as usize);
-    (unpadded_bytes_per_row, padded_bytes_per_row, buffer_size)
+/// Points the redirected cameras back at their window, and removes the capture image along with its presenter.
+fn restore_capture_target(
+    commands: &mut Commands,
+    target: &mut GifCaptureTarget,
+    cameras: &mut Query<(Entity, &mut Camera)>,
+) {
+    for (entity, window, viewport) in target.cameras.drain(..) {
+        if let Ok((_, mut camera)) = cameras.get_mut(entity) {
+            camera.target = RenderTarget::Window(window);
+            camera.viewport = viewport;
+        }
+    }
+    for entity in target.presenter.drain(..) {
+        commands.entity(entity).despawn();
+    }
+    target.image = None;
 }
 
-/// Creates the buffer for saving the gif based on the Windows size.
-fn create_buffer(mut commands: Commands, render_device: Res<RenderDevice>, windows: Res<Windows>) {
-    let primary_window = windows.primary();
-    let (buffer_size, _, _) = get_buffer_size(primary_window);
-    let buffer_desc = BufferDescriptor {
+/// Creates an image the window's cameras can render into, and that can be copied from.
+fn create_capture_image(size: Extent3d) -> Image {
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("Gif Capture Image"),
+            size,
+            dimension: TextureDimension::D2,
+            // The format the pipelines of the cameras are specialized for, which is Bgra on most platforms.
+            // Frames are converted to the Rgba the encoder expects when they're read back.
+            format: TextureFormat::bevy_default(),
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+    image.resize(size);
+    image
+}
+
+/// Identifies a single capture, from the event that starts it to the events reporting its results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CaptureId(u64);
+
+impl fmt::Display for CaptureId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl CaptureId {
+    /// Creates an id that isn't used by any other capture.
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        CaptureId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Starts capturing a gif with the current `GifCaptureSettings`. Other sources can be captured at the same time,
+/// each into its own file, while starting a source that is already being captured fails.
+/// That includes a window and a camera rendering to it, since capturing the window already captures the camera.
+/// The id of the capture is picked when the event is created, so it can be kept around to control the capture.
+/// Any option set on the event is used instead of the one in the settings, for this capture only:
+///
+/// ```ignore
+/// let mut start = GifCaptureStartEvent::new();
+/// start.duration = Some(2.0);
+/// let id = start.id();
+/// start_events.send(start);
+/// ```
+pub struct GifCaptureStartEvent {
+    id: CaptureId,
+    /// The window to capture, instead of the source in the settings.
+    pub window: Option<WindowId>,
+    pub path: Option<PathBuf>,
+    pub directory: Option<PathBuf>,
+    pub duration: Option<f32>,
+    /// Overrides `frames` of the settings, capturing exactly this many frames.
+    pub frames: Option<u32>,
+    pub speed: Option<i32>,
+    pub repeat: Option<Repeat>,
+    pub region: Option<CaptureRegion>,
+    /// Overrides `target_fps` of the settings.
+    pub fps: Option<f32>,
+}
+
+impl GifCaptureStartEvent {
+    /// Starts a capture of the source in the settings.
+    pub fn new() -> Self {
+        GifCaptureStartEvent {
+            id: CaptureId::new(),
+            window: None,
+            path: None,
+            directory: None,
+            duration: None,
+            frames: None,
+            speed: None,
+            repeat: None,
+            region: None,
+            fps: None,
+        }
+    }
+
+    /// Starts a capture of the window.
+    pub fn window(window: WindowId) -> Self {
+        GifCaptureStartEvent {
+            window: Some(window),
+            ..GifCaptureStartEvent::new()
+        }
+    }
+
+    pub fn id(&self) -> CaptureId {
+        self.id
+    }
+
+    /// The settings of the capture: the given ones, with the options set on the event replaced.
+    fn settings(&self, settings: &GifCaptureSettings) -> GifCaptureSettings {
+        let mut settings = settings.clone();
+        if let Some(window) = self.window {
+            settings.source = CaptureSource::Window(window);
+        }
+        if let Some(path) = &self.path {
+            settings.path = path.clone();
+        }
+        if let Some(directory) = &self.directory {
+            settings.directory = directory.clone();
+        }
+        if let Some(duration) = self.duration {
+            settings.duration = duration;
+        }
+        if let Some(frames) = self.frames {
+            settings.frames = Some(frames);
+        }
+        if let Some(speed) = self.speed {
+            settings.speed = speed;
+        }
+        if let Some(repeat) = self.repeat {
+            settings.repeat = repeat;
+        }
+        if let Some(region) = self.region {
+            settings.region = Some(region);
+        }
+        if let Some(fps) = self.fps {
+            settings.target_fps = Some(fps);
+        }
+        settings
+    }
+}
+
+impl Default for GifCaptureStartEvent {
+    fn default() -> Self {
+        GifCaptureStartEvent::new()
+    }
+}
+
+/// Finishes the capture early, saving the frames captured so far.
+pub struct GifCaptureStopEvent {
+    pub id: CaptureId,
+}
+/// Pauses the capture. No frames are captured and the duration stops counting down until it is resumed.
+pub struct GifCapturePauseEvent {
+    pub id: CaptureId,
+}
+/// Resumes the capture, if it's paused.
+pub struct GifCaptureResumeEvent {
+    pub id: CaptureId,
+}
+/// Ends the capture without saving anything.
+pub struct GifCaptureCancelEvent {
+    pub id: CaptureId,
+}
+/// Saves the frames currently held by a capture running in `CaptureMode::Replay`, without stopping it.
+pub struct GifCaptureSaveReplayEvent {
+    pub id: CaptureId,
+}
+/// Sent when starting, capturing or saving a gif fails. The capture it happened in is ended, if it was still running,
+/// unless it only failed to save a replay while running.
+#[derive(Debug)]
+pub struct GifCaptureFailedEvent {
+    pub id: CaptureId,
+    pub error: GifCaptureError,
+}
+/// Sent for every gif file a capture wrote, once the capture is done and the file is complete.
+#[derive(Clone, Debug)]
+pub struct GifCaptureFinishedEvent {
+    pub id: CaptureId,
+    pub path: PathBuf,
+    pub frame_count: usize,
+    pub width: u32,
+    pub height: u32,
+    /// How long the gif plays for once, in seconds: the delays of all of its frames added up,
+    /// including the ones played back in reverse.
+    pub duration: f64,
+    /// Time spent resampling, quantizing and writing the frames of the gif.
+    pub encode_time: Duration,
+    /// Size of the finished file, in bytes.
+    pub file_size: u64,
+    /// Frames left out of the gif because the encoder couldn't keep up with the capture.
+    pub dropped_frames: usize,
+}
+pub struct GifCapturePlugin;
+
+/// The frames held on to by a capture in replay mode. Frames of a normal capture go straight to the encoder instead.
+/// Shared, so saving a replay that keeps running doesn't copy them.
+#[derive(Default)]
+pub struct GifCaptureFrames(VecDeque<Arc<CapturedFrame>>);
+
+/// A single frame read back from the captured source, at its physical size.
+#[derive(Clone)]
+struct CapturedFrame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    scale_factor: f64,
+    /// Seconds from the start of the gif to when the frame is shown.
+    timestamp: f64,
+}
+
+/// Results of captures from the Render world or from an encoder thread, waiting to be sent as events in the App world,
+/// along with counters for the progress of the captures. The same outbox is shared by both worlds.
+#[derive(Clone, Default)]
+struct GifCaptureOutbox {
+    reports: Arc<Mutex<Vec<GifCaptureReport>>>,
+    /// Frames read back from the GPU, ever.
+    frames_captured: Arc<AtomicUsize>,
+    /// Frames handed to an encoder, ever.
+    frames_queued: Arc<AtomicUsize>,
+    /// Frames an encoder is done with, ever. Frames it couldn't encode because of an error count as well.
+    frames_encoded: Arc<AtomicUsize>,
+    /// Frames left out because the queue of their encoder was full, ever.
+    frames_dropped: Arc<AtomicUsize>,
+}
+
+enum GifCaptureReport {
+    /// An error, along with the id of the capture it happened in.
+    Failed(CaptureId, GifCaptureError),
+    /// A replay that couldn't be saved, which keeps running.
+    NotSaved(CaptureId, GifCaptureError),
+    /// Every frame of a capture of a number of frames was read back, so the capture can finish.
+    Captured(CaptureId),
+    Finished(GifCaptureFinishedEvent),
+}
+
+impl GifCaptureOutbox {
+    fn report_error(&self, capture: CaptureId, error: GifCaptureError) {
+        self.report(GifCaptureReport::Failed(capture, error));
+    }
+
+    fn report_not_saved(&self, capture: CaptureId, error: GifCaptureError) {
+        self.report(GifCaptureReport::NotSaved(capture, error));
+    }
+
+    fn report_captured(&self, capture: CaptureId) {
+        self.report(GifCaptureReport::Captured(capture));
+    }
+
+    fn report_finished(&self, finished: GifCaptureFinishedEvent) {
+        self.report(GifCaptureReport::Finished(finished));
+    }
+
+    fn report(&self, report: GifCaptureReport) {
+        if let Ok(mut reports) = self.reports.lock() {
+            reports.push(report);
+        }
+    }
+
+    fn take(&self) -> Vec<GifCaptureReport> {
+        match self.reports.lock() {
+            Ok(mut reports) => mem::take(&mut *reports),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn count_captured(&self) {
+        self.frames_captured.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn count_queued(&self, frames: usize) {
+        self.frames_queued.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    fn count_encoded(&self, frames: usize) {
+        self.frames_encoded.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    fn count_dropped(&self) {
+        self.frames_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Sends the reported results as events. Errors also cancel the capture they happened in,
+/// and captures of a number of frames finish once all of their frames are read back.
+fn send_capture_events(
+    outbox: Res<GifCaptureOutbox>,
+    mut captures: ResMut<GifCaptures>,
+    mut failed_events: EventWriter<GifCaptureFailedEvent>,
+    mut finished_events: EventWriter<GifCaptureFinishedEvent>,
+) {
+    for report in outbox.take() {
+        match report {
+            GifCaptureReport::Failed(id, error) => {
+                if let Some(capture) = captures
+                    .captures
+                    .iter_mut()
+                    .find(|capture| capture.id == id && capture.state.is_active())
+                {
+                    capture.state = GifCaptureState::Cancelled;
+                }
+                failed_events.send(GifCaptureFailedEvent { id, error });
+            }
+            GifCaptureReport::NotSaved(id, error) => {
+                failed_events.send(GifCaptureFailedEvent { id, error })
+            }
+            GifCaptureReport::Captured(id) => {
+                if let Some(capture) = captures
+                    .captures
+                    .iter_mut()
+                    .find(|capture| capture.id == id && capture.state.is_active())
+                {
+                    capture.state = GifCaptureState::JustFinishedCapturing;
+                }
+            }
+            GifCaptureReport::Finished(finished) => finished_events.send(finished),
+        }
+    }
+}
+
+/// The events controlling the captures.
+#[derive(SystemParam)]
+struct GifCaptureEvents<'w, 's> {
+    start: EventReader<'w, 's, GifCaptureStartEvent>,
+    stop: EventReader<'w, 's, GifCaptureStopEvent>,
+    pause: EventReader<'w, 's, GifCapturePauseEvent>,
+    resume: EventReader<'w, 's, GifCaptureResumeEvent>,
+    cancel: EventReader<'w, 's, GifCaptureCancelEvent>,
+}
+
+/// Starts new captures and moves the running ones along, based on the capture events and their timers.
+fn update_capture_state(
+    mut captures: ResMut<GifCaptures>,
+    time: Res<Time>,
+    settings: Res<GifCaptureSettings>,
+    mut events: GifCaptureEvents,
+    sources: CaptureSources,
+    outbox: Res<GifCaptureOutbox>,
+    movie: Res<MovieTime>,
+) {
+    let real_delta = movie.real.as_ref().unwrap_or(&*time).delta();
+    // The render world gets to see finished and cancelled captures for exactly one frame.
+    captures
+        .captures
+        .retain(|capture| capture.state.is_active());
+    for event in events.start.iter() {
+        let settings = event.settings(&settings);
+        if let Err(error) = settings
+            .validate()
+            .and_then(|_| captures.start(event.id, settings, &sources))
+        {
+            outbox.report_error(event.id, error);
+        }
+    }
+    let paused = events
+        .pause
+        .iter()
+        .map(|event| event.id)
+        .collect::<Vec<_>>();
+    let resumed = events
+        .resume
+        .iter()
+        .map(|event| event.id)
+        .collect::<Vec<_>>();
+    let stopped = events.stop.iter().map(|event| event.id).collect::<Vec<_>>();
+    let cancelled = events
+        .cancel
+        .iter()
+        .map(|event| event.id)
+        .collect::<Vec<_>>();
+    for capture in captures.captures.iter_mut() {
+        let pause = paused.contains(&capture.id);
+        let resume = resumed.contains(&capture.id);
+        let stop = stopped.contains(&capture.id);
+        let cancel = cancelled.contains(&capture.id);
+        let gif_time = &mut capture.time;
+        if pause && capture.state == GifCaptureState::CurrentlyCapturing {
+            gif_time.timer.pause();
+            capture.state = GifCaptureState::Paused;
+        }
+        if resume && capture.state == GifCaptureState::Paused {
+            gif_time.timer.unpause();
+            capture.state = GifCaptureState::CurrentlyCapturing;
+        }
+        if stop && capture.state.is_active() {
+            capture.state = GifCaptureState::JustFinishedCapturing;
+        }
+        if cancel && capture.state.is_active() {
+            capture.state = GifCaptureState::Cancelled;
+        }
+        gif_time.timer.tick(time.delta());
+        if capture.state == GifCaptureState::CurrentlyCapturing {
+            gif_time.elapsed += match capture.settings.timing {
+                CaptureTiming::SlowMotion { .. } => real_delta.as_secs_f64(),
+                _ => time.delta_seconds_f64(),
+            };
+        }
+        // Replays run until they get stopped, and captures of a number of frames until the Render world has read them all back.
+        let done = capture.settings.frames.is_none() && gif_time.timer.just_finished();
+        if done
+            && capture.state == GifCaptureState::CurrentlyCapturing
+            && !matches!(capture.settings.mode, CaptureMode::Replay { .. })
+        {
+            capture.state = GifCaptureState::JustFinishedCapturing;
+        }
+        // Decided here rather than in the Render world, so frames in between the ones needed for the target frame rate
+        // are never copied at all.
+        capture.capture_frame = capture.state == GifCaptureState::CurrentlyCapturing
+            && match capture.settings.mode {
+                CaptureMode::TimeLapse {
+                    every: TimeLapseInterval::Frames(frames),
+                    ..
+                } => capture.frames_rendered % frames as u64 == 0,
+                _ => gif_time.elapsed >= capture.next_frame,
+            };
+        if capture.state == GifCaptureState::CurrentlyCapturing {
+            capture.frames_rendered += 1;
+        }
+        if capture.capture_frame {
+            capture.timestamp = match capture.settings.mode {
+                CaptureMode::TimeLapse { fps, .. } => capture.frames_picked as f64 / fps as f64,
+                _ => gif_time.elapsed,
+            };
+            capture.frames_picked += 1;
+        }
+        if let Some(interval) = capture
+            .settings
+            .frame_interval()
+            .filter(|_| capture.capture_frame)
+        {
+            // Staying on a fixed grid, so uneven frame times don't add up to a lower frame rate.
+            capture.next_frame = ((gif_time.elapsed / interval).floor() + 1.0) * interval;
+        }
+    }
+}
+
+/// The `Time` the app sees while a capture in `CaptureTiming::Movie` or `CaptureTiming::SlowMotion` runs.
+#[derive(Default)]
+struct MovieTime {
+    /// Advanced by the fixed timestep or the scaled real time every frame, starting from the real time when the capture started.
+    time: Option<Time>,
+    /// The real time, put back at the end of every frame, so it keeps measuring real frames.
+    real: Option<Time>,
+}
+
+/// Swaps in the movie time for the rest of the frame, while a capture in `CaptureTiming::Movie` or `CaptureTiming::SlowMotion` runs.
+/// Runs in `CoreStage::First`, after `Time` got updated, since that happens at the start of the stage.
+fn apply_movie_time(
+    mut time: ResMut<Time>,
+    mut movie: ResMut<MovieTime>,
+    captures: Res<GifCaptures>,
+) {
+    let step = captures
+        .captures
+        .iter()
+        .filter(|capture| capture.state.is_active())
+        .find_map(|capture| match capture.settings.timing {
+            CaptureTiming::Movie { fps } => Some(Duration::from_secs_f64(1.0 / fps as f64)),
+            CaptureTiming::SlowMotion { scale } => Some(time.delta().mul_f32(scale)),
+            CaptureTiming::RealTime => None,
+        });
+    let step = match step {
+        Some(step) => step,
+        None => {
+            movie.time = None;
+            return;
+        }
+    };
+    let movie_time = movie.time.get_or_insert_with(|| time.clone());
+    let last_update = movie_time
+        .last_update()
+        .unwrap_or_else(|| movie_time.startup());
+    movie_time.update_with_instant(last_update + step);
+    let movie_time = movie_time.clone();
+    movie.real = Some(mem::replace(&mut *time, movie_time));
+}
+
+/// Puts the real time back at the end of the frame.
+fn restore_real_time(mut time: ResMut<Time>, mut movie: ResMut<MovieTime>) {
+    if let Some(real) = movie.real.take() {
+        *time = real;
+    }
+}
+
+/// Progress of the running captures and of the gifs being encoded, kept up to date for things like progress bars.
+/// When several captures run at the same time, it describes all of them together.
+#[derive(Clone, Debug, Default)]
+pub struct GifCaptureProgress {
+    /// Whether a capture is running, paused or not.
+    pub capturing: bool,
+    /// Whether every running capture is paused.
+    pub paused: bool,
+    /// Seconds captured so far, not counting the time spent paused, by the capture that has been running the longest.
+    pub elapsed: f32,
+    /// Seconds left until every capture finishes on its own. `None` when a replay is running, since those run until they're stopped,
+    /// or a capture of a number of frames, which can't tell how long those take.
+    pub remaining: Option<f32>,
+    /// Frames read back from the GPU since the first of the running captures started.
+    pub frames_captured: usize,
+    /// Frames left out since the first of the running captures started, because the encoder couldn't keep up.
+    pub frames_dropped: usize,
+    /// How far along encoding the captured frames is, from 0.0 to 1.0. Only `Some` while frames are waiting on the encoder,
+    /// which mostly happens after a capture stops or while a replay is being saved.
+    pub encoding: Option<f32>,
+    /// Value of the outbox capture counter when the first of the running captures started.
+    captured_at_start: usize,
+    /// Value of the outbox drop counter when the first of the running captures started.
+    dropped_at_start: usize,
+    /// Value of the outbox encoding counters the last time the encoder had caught up.
+    encoded_at_idle: usize,
+}
+
+fn update_capture_progress(
+    mut progress: ResMut<GifCaptureProgress>,
+    captures: Res<GifCaptures>,
+    outbox: Res<GifCaptureOutbox>,
+) {
+    let frames_captured = outbox.frames_captured.load(Ordering::Relaxed);
+    let frames_dropped = outbox.frames_dropped.load(Ordering::Relaxed);
+    let active = captures
+        .captures
+        .iter()
+        .filter(|capture| capture.state.is_active())
+        .collect::<Vec<_>>();
+    if !active.is_empty() && !progress.capturing {
+        progress.captured_at_start = frames_captured;
+        progress.dropped_at_start = frames_dropped;
+    }
+    progress.capturing = !active.is_empty();
+    progress.paused = progress.capturing
+        && active
+            .iter()
+            .all(|capture| capture.state == GifCaptureState::Paused);
+    if progress.capturing {
+        progress.elapsed = active
+            .iter()
+            .map(|capture| capture.time.elapsed as f32)
+            .fold(0.0, f32::max);
+        progress.remaining = active
+            .iter()
+            .map(|capture| match capture.settings.mode {
+                CaptureMode::Clip | CaptureMode::TimeLapse { .. }
+                    if capture.settings.frames.is_some() =>
+                {
+                    None
+                }
+                CaptureMode::Clip | CaptureMode::TimeLapse { .. } => {
+                    let timer = &capture.time.timer;
+                    let remaining = (timer.duration() - timer.elapsed()).as_secs_f32();
+                    // The timer counts game time, which runs slower than the real time left.
+                    match capture.settings.timing {
+                        CaptureTiming::SlowMotion { scale } => Some(remaining / scale),
+                        _ => Some(remaining),
+                    }
+                }
+                CaptureMode::Replay { .. } => None,
+            })
+            .try_fold(0.0, |remaining, capture_remaining| {
+                capture_remaining.map(|capture_remaining| f32::max(remaining, capture_remaining))
+            });
+        progress.frames_captured = frames_captured - progress.captured_at_start;
+        progress.frames_dropped = frames_dropped - progress.dropped_at_start;
+    }
+    // Encoded is loaded first, so it can never be ahead of queued.
+    let frames_encoded = outbox.frames_encoded.load(Ordering::Relaxed);
+    let frames_queued = outbox.frames_queued.load(Ordering::Relaxed);
+    if frames_encoded >= frames_queued {
+        progress.encoded_at_idle = frames_queued;
+        progress.encoding = None;
+    } else {
+        let done = frames_encoded - progress.encoded_at_idle;
+        let total = frames_queued - progress.encoded_at_idle;
+        progress.encoding = Some(done as f32 / total as f32);
+    }
+}
+
+/// Core plugin for capturing gifs.
+impl Plugin for GifCapturePlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<GifCaptureSettings>();
+        app.init_resource::<GifCaptures>();
+        app.init_resource::<GifCaptureProgress>();
+        app.init_resource::<MovieTime>();
+        app.add_event::<GifCaptureStartEvent>()
+            .add_event::<GifCaptureStopEvent>()
+            .add_event::<GifCapturePauseEvent>()
+            .add_event::<GifCaptureResumeEvent>()
+            .add_event::<GifCaptureCancelEvent>()
+            .add_event::<GifCaptureSaveReplayEvent>()
+            .add_event::<GifCaptureFailedEvent>()
+            .add_event::<GifCaptureFinishedEvent>();
+        let outbox = GifCaptureOutbox::default();
+        app.insert_resource(outbox.clone());
+        app.add_system(update_capture_state);
+        app.add_system(send_capture_events.after(update_capture_state));
+        app.add_system(update_capture_progress.after(send_capture_events));
+        app.add_system(update_capture_targets.after(send_capture_events));
+        app.add_system_to_stage(CoreStage::First, apply_movie_time);
+        app.add_system_to_stage(CoreStage::Last, restore_real_time);
+        static GET_GIF_DATA: &str = "get_gif_data";
+        static GIF_CAPTURE: &str = "gif_capture";
+        let render_app = match app.get_sub_app_mut(RenderApp) {
+            Ok(render_app) => render_app,
+            // Nothing to capture without rendering.
+            Err(_) => return,
+        };
+        render_app
+            .insert_resource(outbox)
+            .init_resource::<ExtractedGifCaptures>()
+            .init_resource::<GifCaptureSessions>()
+            .add_system_to_stage(RenderStage::Extract, extract_gif_captures)
+            // After the images are prepared, so the size a buffer is picked for is the size of the image that gets copied.
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                prepare_gif_buffers.after(PrepareAssetLabel::PreAssetPrepare),
+            )
+            .add_stage_before(
+                RenderStage::Cleanup,
+                GET_GIF_DATA,
+                SystemStage::single_threaded(),
+            )
+            .add_system_to_stage(GET_GIF_DATA, read_gif_buffers)
+            .add_system_to_stage(GET_GIF_DATA, save_gif_on_state.after(read_gif_buffers));
+
+        let mut render_graph = render_app.world.get_resource_mut::<RenderGraph>().unwrap();
+        render_graph.add_node(GIF_CAPTURE, DispatchGifCapture {});
+        // Every camera has rendered once the camera driver is done, including the ones rendering into the capture image.
+        render_graph
+            .add_node_edge(CAMERA_DRIVER, GIF_CAPTURE)
+            .unwrap();
+    }
+}
+
+/// How many frames can be waiting on the GPU to be read back at the same time.
+const STAGING_BUFFER_COUNT: usize = 3;
+
+/// A buffer the capture image is copied into, and read back from once the GPU is done with it.
+/// The size is the physical size of the captured region, in pixels.
+struct StagingBuffer {
+    buffer: Buffer,
+    /// Where the captured region starts in the image.
+    origin: Origin3d,
+    /// Size of the image the region was picked for.
+    image_size: Vec2,
+    /// Format of the image, which decides the order of the color channels in the buffer.
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    scale_factor: f64,
+    /// Capture time of the frame copied into the buffer.
+    timestamp: f64,
+    /// Set by `DispatchGifCapture` once the frame was actually copied into the buffer.
+    copied: AtomicBool,
+    /// Filled in by the `map_async` callback, once the buffer can be read.
+    mapped: Arc<Mutex<Option<Result<(), BufferAsyncError>>>>,
+}
+
+/// A small ring of staging buffers, so reading back a frame never has to wait on the GPU
+/// while the frames after it are still being rendered.
+#[derive(Default)]
+struct GifBufferPool {
+    buffers: Vec<StagingBuffer>,
+    /// Indices of the buffers that can be copied into.
+    free: Vec<usize>,
+    /// Index of the buffer this frame gets copied into, if any.
+    current: Option<usize>,
+    /// Indices of the buffers waiting to be mapped, oldest frame first.
+    in_flight: VecDeque<usize>,
+}
+
+impl GifBufferPool {
+    fn current_buffer(&self) -> Option<&StagingBuffer> {
+        self.current.map(|index| &self.buffers[index])
+    }
+}
+
+/// What the Render world holds on to for each capture, from its first captured frame until it ends.
+#[derive(Default)]
+struct GifCaptureSession {
+    /// The image copied from this frame.
+    image: Option<Handle<Image>>,
+    /// Frames still to be read back, for captures of a number of frames.
+    frames_left: Option<u32>,
+    /// Capture time at which the last frame was read back, or the session started.
+    progress_at: f64,
+    pool: GifBufferPool,
+    frames: GifCaptureFrames,
+    encoder: GifEncoderChannel,
+}
+
+/// The sessions of the running captures, by capture id.
+#[derive(Default)]
+struct GifCaptureSessions(HashMap<CaptureId, GifCaptureSession>);
+
+/// Gets the buffer size needed to capture an entire window, where each pixel is a u32 color.
+/// Output: (unpadded_bytes_per_row, padded_bytes_per_row, total_buffer_size)
+fn get_buffer_size(width: u32, height: u32) -> (u32, usize, usize) {
+    let pixel_size = mem::size_of::<[u8; 4]>() as u32;
+    let unpadded_bytes_per_row = pixel_size * width;
+    let padded_bytes_per_row =
+        RenderDevice::align_copy_bytes_per_row(unpadded_bytes_per_row as usize);
+    let buffer_size = padded_bytes_per_row * (height as usize);
+    (unpadded_bytes_per_row, padded_bytes_per_row, buffer_size)
+}
+
+fn create_staging_buffer(render_device: &RenderDevice, width: u32, height: u32) -> Buffer {
+    let (_, _, buffer_size) = get_buffer_size(width, height);
+    let buffer_desc = BufferDescriptor {
         size: buffer_size as u64,
         usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
         label: Some("Gif Output Buffer"),
         mapped_at_creation: false,
     };
-    let output_buffer = render_device.create_buffer(&buffer_desc);
-    commands.insert_resource(GifBuffer(output_buffer));
+    render_device.create_buffer(&buffer_desc)
 }
 
-/// Writes the gif from the buffer, back into our frames resource.
-fn write_gif(
-    mut frames: ResMut<GifCaptureFrames>,
-    buffer: Res<GifBuffer>,
+/// Picks the staging buffer each capture's image gets copied into this frame, sized to the captured region of the image.
+/// Buffers are only recreated when the image size changes.
+/// If every buffer of a capture is still waiting on the GPU, its frame is skipped rather than stalling the render thread.
+fn prepare_gif_buffers(
+    mut sessions: ResMut<GifCaptureSessions>,
     render_device: Res<RenderDevice>,
-    windows: Res<Windows>,
+    gpu_images: Res<RenderAssets<Image>>,
+    captures: Res<ExtractedGifCaptures>,
+    outbox: Res<GifCaptureOutbox>,
 ) {
-    let primary_window = windows.get_primary().unwrap();
-    let (unpadded_bytes_per_row, padded_bytes_per_row, _) = get_buffer_size(primary_window);
-    let buffer_slice = buffer.0.slice(..);
-    render_device.map_buffer(&buffer_slice, MapMode::Read);
-    let padded_data = buffer_slice.get_mapped_range();
-    let data = padded_data
-        .chunks(padded_bytes_per_row as _)
-        .map(|chunk| &chunk[..unpadded_bytes_per_row as _])
-        .flatten()
-        .map(|x| *x)
-        .collect::<Vec<_>>();
-    drop(padded_data);
-    //output_buffer.unmap();
-    frames.0.push(data);
+    for session in sessions.0.values_mut() {
+        session.pool.current = None;
+    }
+    for capture in &captures.0 {
+        // The frame gets picked before the capture can be ended by an error reported the frame before.
+        if !capture.capture_frame || capture.state != GifCaptureState::CurrentlyCapturing {
+            continue;
+        }
+        let session = sessions
+            .0
+            .entry(capture.id)
+            .or_insert_with(|| GifCaptureSession {
+                frames_left: match capture.settings.mode {
+                    CaptureMode::Replay { .. } => None,
+                    _ => capture.settings.frames,
+                },
+                progress_at: capture.elapsed,
+                ..default()
+            });
+        session.image = capture.image.clone();
+        // Every frame still needed is already on its way.
+        if session.frames_left.map_or(false, |frames_left| {
+            frames_left as usize <= session.pool.in_flight.len()
+        }) {
+            continue;
+        }
+        // The image only shows up here a frame after it was created.
+        let gpu_image = match capture
+            .image
+            .as_ref()
+            .and_then(|image| gpu_images.get(image))
+        {
+            Some(gpu_image) => gpu_image,
+            None => continue,
+        };
+        let full = CaptureRegion {
+            x: 0,
+            y: 0,
+            width: gpu_image.size.x as u32,
+            height: gpu_image.size.y as u32,
+        };
+        // The region of the settings is measured from the corner of the viewport, when only that is captured.
+        let region = capture
+            .viewport
+            .map_or(Some(full), |viewport| viewport.within(full))
+            .and_then(|source| match capture.settings.region {
+                Some(region) => region.within(source),
+                None => Some(source),
+            });
+        let region = match region {
+            Some(region) => region,
+            // Only windows can be checked when the capture starts, other sources are only known once they're rendered.
+            None => {
+                if let Some(region) = capture.settings.region {
+                    outbox.report_error(capture.id, GifCaptureError::InvalidRegion(region));
+                }
+                continue;
+            }
+        };
+        let (width, height) = (region.width, region.height);
+        let pool = &mut session.pool;
+        let index = if let Some(index) = pool.free.pop() {
+            index
+        } else if pool.buffers.len() < STAGING_BUFFER_COUNT || capture.settings.keeps_every_frame()
+        {
+            // Captures that keep every frame can't skip any, so they get as many buffers as they need.
+            pool.buffers.push(StagingBuffer {
+                buffer: create_staging_buffer(&render_device, width, height),
+                origin: Origin3d::ZERO,
+                image_size: gpu_image.size,
+                format: gpu_image.texture_format,
+                width,
+                height,
+                scale_factor: 1.0,
+                timestamp: 0.0,
+                copied: AtomicBool::new(false),
+                mapped: Arc::new(Mutex::new(None)),
+            });
+            pool.buffers.len() - 1
+        } else {
+            continue;
+        };
+        let staging = &mut pool.buffers[index];
+        if (staging.width, staging.height) != (width, height) {
+            staging.buffer = create_staging_buffer(&render_device, width, height);
+            staging.width = width;
+            staging.height = height;
+        }
+        staging.origin = Origin3d {
+            x: region.x,
+            y: region.y,
+            z: 0,
+        };
+        staging.image_size = gpu_image.size;
+        staging.format = gpu_image.texture_format;
+        staging.scale_factor = capture.scale_factor;
+        staging.timestamp = capture.timestamp;
+        pool.current = Some(index);
+    }
+}
+
+/// Starts mapping the buffers copied into this frame, and hands every frame the GPU is done with to the encoder of its capture,
+/// or to the replay buffer in replay mode.
+/// Only waits on the GPU when a capture ends, since every frame is needed before saving.
+fn read_gif_buffers(
+    mut sessions: ResMut<GifCaptureSessions>,
+    render_device: Res<RenderDevice>,
+    captures: Res<ExtractedGifCaptures>,
+    outbox: Res<GifCaptureOutbox>,
+) {
+    for session in sessions.0.values_mut() {
+        let pool = &mut session.pool;
+        if let Some(index) = pool.current.take() {
+            let staging = &pool.buffers[index];
+            // Nothing to read from a buffer the frame couldn't be copied into.
+            if !staging.copied.swap(false, Ordering::Relaxed) {
+                pool.free.push(index);
+                continue;
+            }
+            let mapped = staging.mapped.clone();
+            staging
+                .buffer
+                .slice(..)
+                .map_async(MapMode::Read, move |result| {
+                    if let Ok(mut mapped) = mapped.lock() {
+                        *mapped = Some(result);
+                    }
+                });
+            pool.in_flight.push_back(index);
+        }
+    }
+    let finishing = captures.0.iter().any(|capture| {
+        matches!(
+            capture.state,
+            GifCaptureState::JustFinishedCapturing | GifCaptureState::Cancelled
+        )
+    });
+    render_device.poll(if finishing {
+        Maintain::Wait
+    } else {
+        Maintain::Poll
+    });
+    for capture in &captures.0 {
+        let session = match sessions.0.get_mut(&capture.id) {
+            Some(session) => session,
+            None => continue,
+        };
+        let pool = &mut session.pool;
+        while let Some(&index) = pool.in_flight.front() {
+            let result = match pool.buffers[index].mapped.lock() {
+                Ok(mut mapped) => mapped.take(),
+                Err(_) => None,
+            };
+            let result = match result {
+                Some(result) => result,
+                None => break,
+            };
+            pool.in_flight.pop_front();
+            pool.free.push(index);
+            if let Err(error) = result {
+                outbox.report_error(capture.id, GifCaptureError::BufferMap(error));
+                continue;
+            }
+            let staging = &pool.buffers[index];
+            let (unpadded_bytes_per_row, padded_bytes_per_row, _) =
+                get_buffer_size(staging.width, staging.height);
+            let padded_data = staging.buffer.slice(..).get_mapped_range();
+            let mut data = padded_data
+                .chunks(padded_bytes_per_row as _)
+                .flat_map(|chunk| &chunk[..unpadded_bytes_per_row as _])
+                .copied()
+                .collect::<Vec<_>>();
+            drop(padded_data);
+            staging.buffer.unmap();
+            if capture.state == GifCaptureState::Cancelled {
+                continue;
+            }
+            if is_bgra(staging.format) {
+                bgra_to_rgba(&mut data);
+            }
+            // Counted here rather than when the frame is picked, since not every picked frame makes it back.
+            if let Some(frames_left) = &mut session.frames_left {
+                if *frames_left == 0 {
+                    continue;
+                }
+                *frames_left -= 1;
+                if *frames_left == 0 {
+                    outbox.report_captured(capture.id);
+                }
+                session.progress_at = capture.elapsed;
+            }
+            let frame = CapturedFrame {
+                data,
+                width: staging.width,
+                height: staging.height,
+                scale_factor: staging.scale_factor,
+                timestamp: staging.timestamp,
+            };
+            outbox.count_captured();
+            match capture.settings.mode {
+                CaptureMode::Clip | CaptureMode::TimeLapse { .. } => {
+                    session.encoder.send_frame(capture, &outbox, frame)
+                }
+                CaptureMode::Replay { length } => {
+                    push_replay_frame(&mut session.frames, frame, length)
+                }
+            }
+        }
+        // Captures of a number of frames only end once those are read back, which never happens
+        // when nothing can be copied from their source, like an image that never gets prepared.
+        if session
+            .frames_left
+            .map_or(false, |frames_left| frames_left > 0)
+            && capture.state == GifCaptureState::CurrentlyCapturing
+            && capture.elapsed - session.progress_at > capture.settings.duration as f64
+        {
+            outbox.report_error(
+                capture.id,
+                GifCaptureError::Stalled(capture.settings.duration),
+            );
+        }
+    }
+}
+
+/// Whether the format stores the color channels in Bgra order, rather than the Rgba order the encoder expects.
+fn is_bgra(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+    )
 }
 
-/// Saves the gif, if we just got finished capturing. Otherwise does nothing.
+/// Swaps the blue and red channel of every pixel, turning Bgra pixels into Rgba ones.
+fn bgra_to_rgba(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+/// Adds the frame to the replay buffer, dropping the frames that are too old to be part of the replay.
+fn push_replay_frame(frames: &mut GifCaptureFrames, frame: CapturedFrame, length: f32) {
+    let timestamp = frame.timestamp;
+    frames.0.push_back(Arc::new(frame));
+    while let Some(oldest) = frames.0.front() {
+        if timestamp - oldest.timestamp <= length as f64 {
+            break;
+        }
+        frames.0.pop_front();
+    }
+}
+
+/// Finishes the gif of each capture that just finished, or throws it away if the capture got cancelled,
+/// and lets go of the session of the capture. Also saves replays when asked to.
 fn save_gif_on_state(
-    settings: Res<GifCaptureSettings>,
-    state: ResMut<GifCaptureState>,
-    frames: Res<GifCaptureFrames>,
-    windows: Res<Windows>,
-    mut commands: Commands,
+    captures: Res<ExtractedGifCaptures>,
+    mut sessions: ResMut<GifCaptureSessions>,
+    outbox: Res<GifCaptureOutbox>,
 ) {
-    match state.as_ref() {
-        GifCaptureState::Off => {}
-        GifCaptureState::CurrentlyCapturing => {}
-        GifCaptureState::JustFinishedCapturing => {
-            let primary_window = windows.get_primary().unwrap();
-            save_gif(
-                settings.as_ref(),
-                &frames.0,
-                primary_window.width() as u16,
-                primary_window.height() as u16,
-            )
-            .unwrap();
-            commands.insert_resource(GifCaptureState::Off);
+    for capture in &captures.0 {
+        match capture.state {
+            GifCaptureState::CurrentlyCapturing | GifCaptureState::Paused => {
+                if !capture.save_replay {
+                    continue;
+                }
+                if !matches!(capture.settings.mode, CaptureMode::Replay { .. }) {
+                    outbox.report_not_saved(capture.id, GifCaptureError::NotAReplay);
+                } else if let Some(session) = sessions
+                    .0
+                    .get(&capture.id)
+                    .filter(|session| !session.frames.0.is_empty())
+                {
+                    // The replay keeps going, so it keeps its frames, sharing them with the encoding task.
+                    let frames = session.frames.0.iter().cloned().collect();
+                    save_gif(capture, &outbox, frames);
+                } else {
+                    // Not a single frame has been read back yet.
+                    outbox.report_not_saved(capture.id, GifCaptureError::NoFrames);
+                }
+            }
+            GifCaptureState::JustFinishedCapturing => {
+                let mut session = sessions.0.remove(&capture.id).unwrap_or_default();
+                if session.encoder.is_started() {
+                    session.encoder.finish(EncoderMessage::Finish);
+                } else if !session.frames.0.is_empty() {
+                    save_gif(capture, &outbox, session.frames.0.into());
+                } else {
+                    // Not a single frame made it back from the GPU, so there is no gif to save.
+                    outbox.report_error(capture.id, GifCaptureError::NoFrames);
+                }
+            }
+            GifCaptureState::Cancelled => {
+                if let Some(mut session) = sessions.0.remove(&capture.id) {
+                    session.encoder.finish(EncoderMessage::Cancel);
+                }
+            }
         }
     }
+    // Sessions of captures the Render world never saw end, which keep their encoder thread waiting otherwise.
+    sessions
+        .0
+        .retain(|id, _| captures.0.iter().any(|capture| capture.id == *id));
 }
 
-/// Creates a file, encodes the data from the Frames resource into the GIF format, and writes that data into the file.
-fn save_gif(
+/// How many read back frames can be waiting on the encoder thread. Frames read back while the queue is full are dropped,
+/// which keeps the memory used by a capture bounded, no matter how long it runs.
+/// They show up in `GifCaptureFinishedEvent::dropped_frames` and `GifCaptureProgress::frames_dropped`.
+const ENCODER_QUEUE_LENGTH: usize = 8;
+
+enum EncoderMessage {
+    Frame(CapturedFrame),
+    /// Finishes the gif, and stops the encoder thread.
+    Finish,
+    /// Deletes everything written so far, and stops the encoder thread.
+    Cancel,
+}
+
+/// The channel to the encoder thread of a capture, which is only started once its first frame is read back.
+#[derive(Default)]
+struct GifEncoderChannel {
+    sender: Option<SyncSender<EncoderMessage>>,
+    /// Frames of the capture left out because the queue was full, shared with the encoder thread to report them.
+    dropped: Arc<AtomicUsize>,
+}
+
+impl GifEncoderChannel {
+    fn send_frame(
+        &mut self,
+        capture: &ExtractedGifCapture,
+        outbox: &GifCaptureOutbox,
+        frame: CapturedFrame,
+    ) {
+        if self.sender.is_none() {
+            let (sender, receiver) = mpsc::sync_channel(ENCODER_QUEUE_LENGTH);
+            let writer = GifWriter::new(capture, self.dropped.clone());
+            let thread_outbox = outbox.clone();
+            // A dedicated thread rather than the task pool, since it spends the whole capture blocked on the channel.
+            let spawned = thread::Builder::new()
+                .name("gif encoder".to_string())
+                .spawn(move || run_encoder(writer, receiver, thread_outbox));
+            match spawned {
+                Ok(_) => self.sender = Some(sender),
+                Err(error) => {
+                    outbox.report_error(capture.id, GifCaptureError::Io(error));
+                    return;
+                }
+            }
+        }
+        if let Some(sender) = &self.sender {
+            // Counted up front, since the encoder could be done with the frame before `try_send` even returns.
+            outbox.count_queued(1);
+            // A full queue means the encoder has fallen behind, and the frame is dropped instead of stalling the render thread,
+            // unless the capture has to keep every frame. Dropped frames are counted, so choppy gifs can be explained.
+            // A disconnected one means the encoder failed, which it already reported.
+            let sent = if capture.settings.keeps_every_frame() {
+                sender.send(EncoderMessage::Frame(frame)).is_ok()
+            } else {
+                match sender.try_send(EncoderMessage::Frame(frame)) {
+                    Ok(()) => true,
+                    Err(TrySendError::Full(_)) => {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        outbox.count_dropped();
+                        false
+                    }
+                    Err(TrySendError::Disconnected(_)) => false,
+                }
+            };
+            if !sent {
+                outbox.count_encoded(1);
+            }
+        }
+    }
+
+    /// Whether a frame was sent, which starts the encoder thread.
+    fn is_started(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Sends the final message to the encoder thread, if there is one, and lets go of it.
+    fn finish(&mut self, message: EncoderMessage) {
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(message);
+        }
+    }
+}
+
+/// Writes frames into the gif as they arrive, until the capture is finished or cancelled.
+/// On failure the partially written files are deleted, since they can't be opened anyway,
+/// and the frames still arriving are skipped.
+fn run_encoder(writer: GifWriter, receiver: Receiver<EncoderMessage>, outbox: GifCaptureOutbox) {
+    let id = writer.id;
+    let mut writer = Some(writer);
+    for message in receiver {
+        match message {
+            EncoderMessage::Frame(frame) => {
+                if let Some(Err(error)) = writer.as_mut().map(|writer| writer.write_frame(frame)) {
+                    if let Some(writer) = writer.take() {
+                        writer.discard();
+                    }
+                    outbox.report_error(id, error);
+                }
+                outbox.count_encoded(1);
+            }
+            EncoderMessage::Finish => break,
+            EncoderMessage::Cancel => {
+                if let Some(writer) = writer.take() {
+                    writer.discard();
+                }
+                return;
+            }
+        }
+    }
+    // The channel closing without a cancel still finishes the gif, since its frames are all there.
+    if let Some(writer) = writer {
+        writer.finish(&outbox);
+    }
+}
+
+/// Encodes frames into gif files one at a time, as they arrive, so no more than a single raw frame is held in memory.
+/// Frames are resampled to the output resolution, and sizes changes are handled according to the resize policy,
+/// so a single capture can end up as several files. Reverse and boomerang playback also hold on to the quantized frames,
+/// which take a quarter of the memory of the raw ones, until the gif is finished.
+struct GifWriter {
+    /// Id of the capture the frames come from.
+    id: CaptureId,
+    settings: GifCaptureSettings,
+    path: PathBuf,
+    /// The gif currently being written, once the first frame has arrived.
+    current: Option<OpenGif>,
+    /// Gifs that have been completely written to their temporary file, along with that file.
+    /// They're only moved to their path once the capture is done, because it could still get cancelled.
+    finished: Vec<(PathBuf, GifCaptureFinishedEvent)>,
+    /// Every temporary file created so far. The last one is the one currently being written.
+    temp_paths: Vec<PathBuf>,
+    /// Frames of the capture the encoder fell too far behind to get, counted by the render thread.
+    dropped: Arc<AtomicUsize>,
+    /// Unique among all writers, so the temporary files of writers saving the same replay to the same path don't clash.
+    number: u64,
+}
+
+struct OpenGif {
+    encoder: Encoder<BufWriter<File>>,
+    /// Where the gif goes once it's done.
+    path: PathBuf,
+    /// Where the gif is written to until then.
+    temp_path: PathBuf,
+    width: u32,
+    height: u32,
+    /// Frames written to the file so far.
+    frame_count: usize,
+    /// The last frame, waiting for the next one to know how long it's shown.
+    pending: Option<Frame<'static>>,
+    /// Centiseconds since the first frame until which the written frames are shown.
+    shown_until: u64,
+    /// Delay of the last frame written.
+    last_delay: u16,
+    /// Quantized frames held on to until the gif is finished, to play them back in reverse.
+    held_frames: Vec<Frame<'static>>,
+    first_timestamp: f64,
+    /// Centiseconds the gif plays for once: the delays of the frames written so far, added up.
+    play_time: u64,
+    encode_time: Duration,
+    /// Frames of the capture dropped before this gif was started.
+    dropped_at_start: usize,
+}
+
+impl OpenGif {
+    fn write(&mut self, frame: &Frame) -> Result<(), GifCaptureError> {
+        self.encoder.write_frame(frame)?;
+        self.frame_count += 1;
+        self.play_time += frame.delay as u64;
+        Ok(())
+    }
+
+    /// Writes a frame whose delay is known, or holds on to it when it's played back in reverse later.
+    fn emit(&mut self, frame: Frame<'static>, playback: Playback) -> Result<(), GifCaptureError> {
+        match playback {
+            Playback::Forward => self.write(&frame)?,
+            Playback::Reverse => self.held_frames.push(frame),
+            Playback::Boomerang => {
+                self.write(&frame)?;
+                self.held_frames.push(frame);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The shortest delay a frame can have, in centiseconds. Most viewers show frames with shorter delays for 10 centiseconds instead,
+/// so frames arriving sooner after the previous one are left out, capping gifs at 50 frames per second.
+const MIN_FRAME_DELAY: u64 = 2;
+
+/// Delay of a frame when there is no next frame to measure it by, in centiseconds.
+const DEFAULT_FRAME_DELAY: u16 = 10;
+
+/// Converts seconds to whole centiseconds, the unit of gif frame delays.
+fn centiseconds(seconds: f64) -> u64 {
+    (seconds.max(0.0) * 100.0).round() as u64
+}
+
+impl GifWriter {
+    fn new(capture: &ExtractedGifCapture, dropped: Arc<AtomicUsize>) -> Self {
+        static NEXT_WRITER: AtomicU64 = AtomicU64::new(0);
+        GifWriter {
+            id: capture.id,
+            settings: capture.settings.clone(),
+            path: capture.path.clone(),
+            current: None,
+            finished: Vec::new(),
+            temp_paths: Vec::new(),
+            dropped,
+            number: NEXT_WRITER.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Resamples and quantizes the frame, then appends it to the current gif.
+    fn write_frame(&mut self, frame: CapturedFrame) -> Result<(), GifCaptureError> {
+        let (width, height) =
+            self.settings
+                .resolution
+                .output_size(frame.width, frame.height, frame.scale_factor);
+        let size_changed = match &self.current {
+            Some(gif) => (gif.width, gif.height) != (width, height),
+            None => true,
+        };
+        if self.current.is_none()
+            || (size_changed && self.settings.resize_policy == ResizePolicy::SplitClip)
+        {
+            self.finish_current()?;
+            self.current = Some(self.create_gif(width, height, frame.timestamp)?);
+        }
+        let gif = match self.current.as_mut() {
+            Some(gif) => gif,
+            None => return Ok(()),
+        };
+        // A frame is shown until the next one, so its delay is only known once the next one arrives.
+        let shown_at = centiseconds(frame.timestamp - gif.first_timestamp);
+        // Too soon after the previous frame to be shown, which keeps showing instead.
+        // Captures that keep every frame play back slower instead.
+        if gif.pending.is_some()
+            && shown_at < gif.shown_until + MIN_FRAME_DELAY
+            && !self.settings.keeps_every_frame()
+        {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let letterbox = (gif.width, gif.height) != (width, height)
+            && self.settings.resize_policy == ResizePolicy::Letterbox;
+        let mut data = if (gif.width, gif.height) == (frame.width, frame.height) {
+            frame.data
+        } else {
+            fit_frame(&frame, gif.width, gif.height, letterbox)
+        };
+        let encoded = Frame::from_rgba_speed(
+            gif.width as u16,
+            gif.height as u16,
+            &mut data,
+            self.settings.speed,
+        );
+        if let Some(mut pending) = gif.pending.take() {
+            // Measured from the start of the gif, so the rounding of one delay is made up for by the next.
+            let delay = shown_at
+                .saturating_sub(gif.shown_until)
+                .clamp(MIN_FRAME_DELAY, u16::MAX as u64) as u16;
+            gif.shown_until += delay as u64;
+            pending.delay = delay;
+            gif.last_delay = delay;
+            gif.emit(pending, self.settings.playback)?;
+        }
+        gif.pending = Some(encoded);
+        gif.encode_time += start.elapsed();
+        Ok(())
+    }
+
+    /// Creates the temporary file of the next gif of the capture, next to where the gif goes.
+    fn create_gif(
+        &mut self,
+        width: u32,
+        height: u32,
+        timestamp: f64,
+    ) -> Result<OpenGif, GifCaptureError> {
+        check_size(width, height)?;
+        let path = clip_path(&self.path, self.temp_paths.len());
+        // Checked up front as well, so no time is spent encoding a gif that can't be saved.
+        if self.settings.overwrite == OverwritePolicy::Fail && path.exists() {
+            return Err(GifCaptureError::AlreadyExists(path));
+        }
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp_path =
+            path.with_file_name(format!(".{}.{}-{}.tmp", file_name, self.id, self.number));
+        let file = BufWriter::new(File::create(&temp_path)?);
+        self.temp_paths.push(temp_path.clone());
+        let mut encoder = Encoder::new(file, width as u16, height as u16, &[])?;
+        encoder.set_repeat(self.settings.repeat)?;
+        Ok(OpenGif {
+            encoder,
+            path,
+            temp_path,
+            width,
+            height,
+            frame_count: 0,
+            pending: None,
+            shown_until: 0,
+            last_delay: DEFAULT_FRAME_DELAY,
+            held_frames: Vec::new(),
+            first_timestamp: timestamp,
+            play_time: 0,
+            encode_time: Duration::ZERO,
+            dropped_at_start: self.dropped.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Finishes the file currently being written, if any.
+    fn finish_current(&mut self) -> Result<(), GifCaptureError> {
+        if let Some(mut gif) = self.current.take() {
+            let start = Instant::now();
+            // Nothing comes after the last frame, so it's shown as long as the one before it.
+            if let Some(mut pending) = gif.pending.take() {
+                pending.delay = gif.last_delay;
+                gif.emit(pending, self.settings.playback)?;
+            }
+            let mut held_frames = mem::take(&mut gif.held_frames);
+            // The delay of a frame is the time until the next one, which comes before it when played back in reverse.
+            // So each frame takes the delay of the frame before it, and the first frame keeps its own.
+            let delays = held_frames
+                .iter()
+                .map(|frame| frame.delay)
+                .collect::<Vec<_>>();
+            let reversed = match self.settings.playback {
+                Playback::Forward => 0..0,
+                Playback::Reverse => 0..held_frames.len(),
+                // The first and the last frame are already shown when the direction changes.
+                Playback::Boomerang => 1..held_frames.len().saturating_sub(1).max(1),
+            };
+            for index in reversed.rev() {
+                let frame = &mut held_frames[index];
+                frame.delay = delays[index.saturating_sub(1)];
+                gif.write(frame)?;
+            }
+            // Dropping the encoder writes the end of the file, but swallows any error doing so.
+            drop(gif.encoder);
+            let file_size = check_written(&gif.temp_path)?;
+            self.finished.push((
+                gif.temp_path,
+                GifCaptureFinishedEvent {
+                    id: self.id,
+                    path: gif.path,
+                    frame_count: gif.frame_count,
+                    width: gif.width,
+                    height: gif.height,
+                    duration: gif.play_time as f64 / 100.0,
+                    encode_time: gif.encode_time + start.elapsed(),
+                    file_size,
+                    dropped_frames: self.dropped.load(Ordering::Relaxed) - gif.dropped_at_start,
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    /// Finishes the last file, moves every file written to its path, and reports them.
+    fn finish(mut self, outbox: &GifCaptureOutbox) {
+        let result = self.finish_current().and_then(|_| {
+            for (temp_path, mut finished) in mem::take(&mut self.finished) {
+                finished.path = self
+                    .settings
+                    .overwrite
+                    .persist(&temp_path, &finished.path)?;
+                outbox.report_finished(finished);
+            }
+            Ok(())
+        });
+        if let Err(error) = result {
+            let id = self.id;
+            self.discard();
+            outbox.report_error(id, error);
+        }
+    }
+
+    /// Stops writing, and deletes every temporary file left. Files at the output paths are never touched.
+    fn discard(mut self) {
+        self.current = None;
+        for path in &self.temp_paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Makes sure a finished gif made it to the disk completely, ending in the gif trailer, and gets its size.
+fn check_written(path: &Path) -> Result<u64, GifCaptureError> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    file.sync_all()?;
+    let file_size = file.metadata()?.len();
+    let mut last = [0u8];
+    if file_size > 0 {
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+    }
+    if last[0] != GIF_TRAILER {
+        return Err(GifCaptureError::Io(io::Error::new(
+            io::ErrorKind::WriteZero,
+            "the end of the gif wasn't written",
+        )));
+    }
+    Ok(file_size)
+}
+
+/// The byte every gif file ends with.
+const GIF_TRAILER: u8 = 0x3B;
+
+/// Gets the path of the nth clip of a capture. The first clip is saved to the path itself,
+/// the ones after it get the index appended to the file name, like `capture_1.gif`.
+fn clip_path(path: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        return path.to_path_buf();
+    }
+    suffixed_path(path, &index.to_string())
+}
+
+/// Gets the path a gif of the capture is saved to at the given time, with the tokens of the settings path filled in.
+fn output_path(
     settings: &GifCaptureSettings,
-    frames: &Vec<Vec<u8>>,
-    width: u16,
-    height: u16,
-) -> Result<(), std::io::Error> {
-    use gif::{Encoder, Frame};
-    let mut image = std::fs::File::create(settings.path).unwrap();
-    let encoder = Encoder::new(&mut image, width, height, &[]);
-    if let Ok(mut encoder) = encoder {
-        encoder.set_repeat(Repeat::Infinite).unwrap();
-        for frame in frames {
-            // Copying because the encoder can change the alpha value on pixels to 0xFF.
-            let mutable_frame: &mut [u8] = &mut frame.clone();
-            encoder
-                .write_frame(&Frame::from_rgba_speed(
-                    width,
-                    height,
-                    mutable_frame,
-                    settings.speed,
-                ))
-                .unwrap();
+    id: CaptureId,
+    number: usize,
+    now: SystemTime,
+) -> PathBuf {
+    let (date, time) = format_date_time(now);
+    let window = match &settings.source {
+        CaptureSource::Window(window) if window.is_primary() => "primary".to_string(),
+        CaptureSource::Window(window) => window.to_string(),
+        CaptureSource::Camera(entity) => format!("camera{}", entity.id()),
+        CaptureSource::Image(_) => "image".to_string(),
+    };
+    let path = settings.directory.join(expand_path(
+        &settings.path,
+        &[
+            ("{date}", date),
+            ("{time}", time),
+            ("{counter}", format!("{:04}", number)),
+            ("{session}", id.to_string()),
+            ("{window}", window.clone()),
+        ],
+    ));
+    // Captures of other windows running at the same time would overwrite each other otherwise.
+    match &settings.source {
+        CaptureSource::Window(other)
+            if !other.is_primary() && !settings.path.to_string_lossy().contains("{window}") =>
+        {
+            suffixed_path(&path, &window)
         }
+        _ => path,
+    }
+}
+
+/// Replaces the tokens in the path with their values.
+fn expand_path(path: &Path, tokens: &[(&str, String)]) -> PathBuf {
+    // Tokens can't be found in paths that aren't valid unicode, which are kept as they are.
+    let mut expanded = match path.to_str() {
+        Some(path) => path.to_string(),
+        None => return path.to_path_buf(),
+    };
+    for (token, value) in tokens {
+        expanded = expanded.replace(token, value);
+    }
+    PathBuf::from(expanded)
+}
+
+/// Formats the date and time in UTC, like `2026-10-18` and `14-03-11`.
+fn format_date_time(time: SystemTime) -> (String, String) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    let time_of_day = seconds % 86_400;
+    // Converts days since the epoch to a date, see: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        format!(
+            "{:02}-{:02}-{:02}",
+            time_of_day / 3600,
+            time_of_day / 60 % 60,
+            time_of_day % 60
+        ),
+    )
+}
+
+/// Appends the suffix to the file name, before the extension, like `capture_suffix.gif`.
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut file_name = format!("{}_{}", stem, suffix);
+    if let Some(extension) = path.extension() {
+        file_name = format!("{}.{}", file_name, extension.to_string_lossy());
+    }
+    path.with_file_name(file_name)
+}
+
+/// Resamples an RGBA frame to the given size, using nearest neighbor sampling.
+/// When `letterbox` is set the aspect ratio of the frame is kept, and the uncovered area is filled with black.
+fn fit_frame(frame: &CapturedFrame, width: u32, height: u32, letterbox: bool) -> Vec<u8> {
+    let (scaled_width, scaled_height) = if letterbox {
+        let scale = f32::min(
+            width as f32 / frame.width as f32,
+            height as f32 / frame.height as f32,
+        );
+        (
+            ((frame.width as f32 * scale) as u32).clamp(1, width),
+            ((frame.height as f32 * scale) as u32).clamp(1, height),
+        )
+    } else {
+        (width, height)
+    };
+    let offset_x = (width - scaled_width) / 2;
+    let offset_y = (height - scaled_height) / 2;
+    let mut data = [0u8, 0, 0, 0xFF].repeat((width * height) as usize);
+    for y in 0..scaled_height {
+        let source_y = (y as u64 * frame.height as u64 / scaled_height as u64) as usize;
+        for x in 0..scaled_width {
+            let source_x = (x as u64 * frame.width as u64 / scaled_width as u64) as usize;
+            let source = (source_y * frame.width as usize + source_x) * 4;
+            let target = ((y + offset_y) * width + x + offset_x) as usize * 4;
+            data[target..target + 4].copy_from_slice(&frame.data[source..source + 4]);
+        }
+    }
+    data
+}
+
+/// Encodes a batch of frames that were already captured, like the ones of a replay, into the GIF format and writes them to disk.
+/// The encoding happens on the `AsyncComputeTaskPool`, so the render thread never waits on it.
+fn save_gif(
+    capture: &ExtractedGifCapture,
+    outbox: &GifCaptureOutbox,
+    frames: Vec<Arc<CapturedFrame>>,
+) {
+    // Replays never drop frames, since they're all there before encoding starts.
+    let mut writer = GifWriter::new(capture, Arc::default());
+    let outbox = outbox.clone();
+    outbox.count_queued(frames.len());
+    AsyncComputeTaskPool::get()
+        .spawn(async move {
+            let frame_count = frames.len();
+            for (index, frame) in frames.into_iter().enumerate() {
+                // Frames the replay still holds on to are copied here, one at a time, rather than on the render thread.
+                let frame = Arc::try_unwrap(frame).unwrap_or_else(|frame| (*frame).clone());
+                if let Err(error) = writer.write_frame(frame) {
+                    let id = writer.id;
+                    writer.discard();
+                    outbox.report_error(id, error);
+                    // The frames that won't be encoded anymore are done as well.
+                    outbox.count_encoded(frame_count - index);
+                    return;
+                }
+                outbox.count_encoded(1);
+            }
+            writer.finish(&outbox);
+        })
+        .detach();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{ecs::event::Events, prelude::App};
+    use gif::{ColorOutput, DecodeOptions};
+
+    /// Creates an empty directory for a single test to write its files in.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gif_capture_test_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn dir_entries(dir: &Path) -> Vec<PathBuf> {
+        let mut entries = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn checks_output_paths() {
+        let dir = test_dir("output_path");
+        assert!(check_output_path(&dir.join("capture.gif")).is_ok());
+        assert!(check_output_path(&dir.join("capture.GIF")).is_ok());
+        assert!(matches!(
+            check_output_path(&dir.join("capture.png")),
+            Err(GifCaptureError::WrongExtension(_))
+        ));
+        assert!(matches!(
+            check_output_path(&dir.join("missing/capture.gif")),
+            Err(GifCaptureError::DirectoryNotFound(_))
+        ));
+        assert!(dir_entries(&dir).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn leaves_files_named_like_probes_alone() {
+        let dir = test_dir("probe");
+        let existing = [
+            dir.join(format!(".gif_capture_probe_{}", process::id())),
+            dir.join(format!(".gif_capture_probe_{}_0", process::id())),
+            dir.join(format!(".gif_capture_probe_{}_1", process::id())),
+        ];
+        for path in &existing {
+            fs::write(path, "keep").unwrap();
+        }
+        for _ in 0..3 {
+            assert!(check_output_path(&dir.join("capture.gif")).is_ok());
+        }
+        assert_eq!(dir_entries(&dir), existing);
+        assert!(existing
+            .iter()
+            .all(|path| fs::read_to_string(path).unwrap() == "keep"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let invalid = [
+            GifCaptureSettings {
+                speed: 0,
+                ..default()
+            },
+            GifCaptureSettings {
+                duration: 0.0,
+                ..default()
+            },
+            GifCaptureSettings {
+                duration: f32::NAN,
+                ..default()
+            },
+            GifCaptureSettings {
+                frames: Some(0),
+                ..default()
+            },
+            GifCaptureSettings {
+                target_fps: Some(-30.0),
+                ..default()
+            },
+            GifCaptureSettings {
+                resolution: OutputResolution::ScaleFactor(0.0),
+                ..default()
+            },
+            GifCaptureSettings {
+                region: Some(CaptureRegion {
+                    x: 0,
+                    y: 0,
+                    width: 100,
+                    height: 0,
+                }),
+                ..default()
+            },
+        ];
+        for settings in invalid {
+            assert!(settings.validate().is_err());
+        }
+        assert!(GifCaptureSettings::default().validate().is_ok());
+    }
+
+    #[test]
+    fn saves_without_replacing_existing_files() {
+        let dir = test_dir("persist");
+        let path = dir.join("capture.gif");
+        let temp_path = dir.join(".capture.gif.tmp");
+        fs::write(&temp_path, "first").unwrap();
+        assert_eq!(
+            OverwritePolicy::Fail.persist(&temp_path, &path).unwrap(),
+            path
+        );
+        fs::write(&temp_path, "second").unwrap();
+        assert!(matches!(
+            OverwritePolicy::Fail.persist(&temp_path, &path),
+            Err(GifCaptureError::AlreadyExists(_))
+        ));
+        fs::write(dir.join("capture_1.gif"), "taken").unwrap();
+        assert_eq!(
+            OverwritePolicy::AutoSuffix
+                .persist(&temp_path, &path)
+                .unwrap(),
+            dir.join("capture_2.gif")
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first");
+        assert_eq!(
+            fs::read_to_string(dir.join("capture_1.gif")).unwrap(),
+            "taken"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("capture_2.gif")).unwrap(),
+            "second"
+        );
+        fs::write(&temp_path, "third").unwrap();
+        assert_eq!(
+            OverwritePolicy::Overwrite
+                .persist(&temp_path, &path)
+                .unwrap(),
+            path
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third");
+        assert!(!temp_path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn test_capture(path: PathBuf, playback: Playback) -> ExtractedGifCapture {
+        ExtractedGifCapture {
+            id: CaptureId(1),
+            state: GifCaptureState::CurrentlyCapturing,
+            settings: GifCaptureSettings {
+                playback,
+                ..default()
+            },
+            path,
+            timestamp: 0.0,
+            elapsed: 0.0,
+            save_replay: false,
+            capture_frame: true,
+            image: None,
+            scale_factor: 1.0,
+            viewport: None,
+        }
+    }
+
+    #[test]
+    fn saves_the_same_replay_twice_at_once() {
+        let dir = test_dir("two_saves");
+        let capture = test_capture(dir.join("capture.gif"), Playback::Forward);
+        let mut first = GifWriter::new(&capture, Arc::default());
+        let mut second = GifWriter::new(&capture, Arc::default());
+        for timestamp in [0.0, 0.1, 0.2] {
+            first.write_frame(solid_frame(4, 4, timestamp)).unwrap();
+            second.write_frame(solid_frame(4, 4, timestamp)).unwrap();
+        }
+        assert_ne!(first.temp_paths, second.temp_paths);
+        let outbox = GifCaptureOutbox::default();
+        first.finish(&outbox);
+        second.finish(&outbox);
+        let saved = outbox
+            .take()
+            .into_iter()
+            .map(|report| match report {
+                GifCaptureReport::Finished(finished) => finished.path,
+                _ => panic!("saving failed"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(saved, [dir.join("capture.gif"), dir.join("capture_1.gif")]);
+        assert_eq!(dir_entries(&dir), saved);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Writes frames with the given timestamps to a gif, and reads back the delay and the pixels of each of its frames.
+    fn written_frames(name: &str, timestamps: &[f64], playback: Playback) -> Vec<(u16, Vec<u8>)> {
+        let dir = test_dir(name);
+        let path = dir.join("capture.gif");
+        let mut writer = GifWriter::new(&test_capture(path.clone(), playback), Arc::default());
+        for (index, &timestamp) in timestamps.iter().enumerate() {
+            let mut frame = solid_frame(4, 4, timestamp);
+            // So every frame looks different.
+            frame.data[1] = index as u8 * 40;
+            writer.write_frame(frame).unwrap();
+        }
+        let outbox = GifCaptureOutbox::default();
+        writer.finish(&outbox);
+        assert!(matches!(
+            outbox.take().as_slice(),
+            [GifCaptureReport::Finished(_)]
+        ));
+        let mut options = DecodeOptions::new();
+        options.set_color_output(ColorOutput::RGBA);
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer.to_vec()));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        frames
+    }
+
+    fn delays(frames: &[(u16, Vec<u8>)]) -> Vec<u16> {
+        frames.iter().map(|(delay, _)| *delay).collect()
+    }
+
+    #[test]
+    fn carries_the_rounding_of_delays() {
+        let timestamps = (0..31).map(|frame| frame as f64 / 30.0).collect::<Vec<_>>();
+        let delays = delays(&written_frames("carry", &timestamps, Playback::Forward));
+        assert_eq!(delays.len(), timestamps.len());
+        assert!(delays.iter().all(|&delay| delay == 3 || delay == 4));
+        // Every frame but the last is shown until the next one, which adds up to the captured second.
+        let shown = delays[..delays.len() - 1]
+            .iter()
+            .map(|&delay| delay as u64)
+            .sum::<u64>();
+        assert_eq!(shown, 100);
+    }
+
+    #[test]
+    fn carries_the_rounding_of_uneven_delays() {
+        let timestamps = [0.0, 0.033, 0.071, 0.1, 0.149, 0.2, 0.236, 0.301];
+        let delays = delays(&written_frames("uneven", &timestamps, Playback::Forward));
+        let shown = delays[..delays.len() - 1]
+            .iter()
+            .map(|&delay| delay as u64)
+            .sum::<u64>();
+        assert_eq!(shown, centiseconds(0.301));
+    }
+
+    #[test]
+    fn rounds_to_centiseconds() {
+        assert_eq!(centiseconds(0.0), 0);
+        assert_eq!(centiseconds(1.0 / 30.0), 3);
+        assert_eq!(centiseconds(2.0), 200);
+        assert_eq!(centiseconds(-1.0), 0);
+    }
+
+    #[test]
+    fn shifts_delays_when_reversed() {
+        let timestamps = [0.0, 0.05, 0.2, 0.3];
+        let forward = written_frames("forward", &timestamps, Playback::Forward);
+        assert_eq!(delays(&forward), [5, 15, 10, 10]);
+        // The frame shown before another one in reverse is the one captured after it.
+        let reverse = written_frames("reverse", &timestamps, Playback::Reverse);
+        assert_eq!(delays(&reverse), [10, 15, 5, 5]);
+        for (reversed, (_, pixels)) in reverse.iter().rev().zip(&forward) {
+            assert_eq!(&reversed.1, pixels);
+        }
+    }
+
+    #[test]
+    fn plays_boomerangs_back_and_forth() {
+        let timestamps = [0.0, 0.05, 0.2, 0.3];
+        let forward = written_frames("boomerang_forward", &timestamps, Playback::Forward);
+        let boomerang = written_frames("boomerang", &timestamps, Playback::Boomerang);
+        // Without showing the last and the first frame twice, so it loops without stopping at either end.
+        assert_eq!(delays(&boomerang), [5, 15, 10, 10, 15, 5]);
+        for (index, original) in [0, 1, 2, 3, 2, 1].into_iter().enumerate() {
+            assert_eq!(boomerang[index].1, forward[original].1);
+        }
+    }
+
+    /// Runs `update_capture_state` for a single capture with the settings, for a frame at each of the given seconds,
+    /// and gets the timestamps of the frames it picks for the gif.
+    fn picked_timestamps(
+        name: &str,
+        settings: GifCaptureSettings,
+        frame_times: &[f64],
+    ) -> Vec<f64> {
+        let dir = test_dir(name);
+        let mut app = App::new();
+        app.insert_resource(GifCaptureSettings {
+            directory: dir.clone(),
+            ..settings
+        })
+        .insert_resource(Time::default())
+        .init_resource::<Windows>()
+        .init_resource::<GifCaptures>()
+        .init_resource::<GifCaptureOutbox>()
+        .init_resource::<MovieTime>()
+        .add_event::<GifCaptureStartEvent>()
+        .add_event::<GifCaptureStopEvent>()
+        .add_event::<GifCapturePauseEvent>()
+        .add_event::<GifCaptureResumeEvent>()
+        .add_event::<GifCaptureCancelEvent>()
+        .add_system(update_capture_state);
+        let start = Instant::now();
+        app.world.resource_mut::<Time>().update_with_instant(start);
+        app.world
+            .resource_mut::<Events<GifCaptureStartEvent>>()
+            .send(GifCaptureStartEvent::new());
+        let mut timestamps = Vec::new();
+        for &frame_time in frame_times {
+            app.world
+                .resource_mut::<Time>()
+                .update_with_instant(start + Duration::from_secs_f64(frame_time));
+            app.update();
+            let captures = &app.world.resource::<GifCaptures>().captures;
+            assert_eq!(captures.len(), 1);
+            timestamps.extend(
+                captures
+                    .iter()
+                    .filter(|capture| capture.capture_frame)
+                    .map(|capture| capture.timestamp),
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        timestamps
+    }
+
+    #[test]
+    fn picks_frames_on_a_fixed_grid() {
+        // Frames alternating between 1/64 and 3/64 of a second, which is 32 frames per second on average.
+        let frame_times = (0..32)
+            .map(|frame| (frame / 2 * 4 + frame % 2) as f64 / 64.0)
+            .collect::<Vec<_>>();
+        let settings = GifCaptureSettings {
+            target_fps: Some(10.0),
+            ..default()
+        };
+        let timestamps = picked_timestamps("grid", settings, &frame_times);
+        // The first frame at or after every tenth of a second, without the uneven frame times adding up.
+        assert_eq!(timestamps.len(), 10);
+        for (index, timestamp) in timestamps.into_iter().enumerate() {
+            let due = index as f64 / 10.0;
+            assert!(timestamp >= due - 1e-9 && timestamp < due + 3.0 / 64.0);
+        }
+    }
+
+    #[test]
+    fn spaces_time_lapse_frames_evenly() {
+        let every_third_frame = GifCaptureSettings {
+            mode: CaptureMode::TimeLapse {
+                every: TimeLapseInterval::Frames(3),
+                fps: 10.0,
+            },
+            ..default()
+        };
+        let frame_times = (0..10).map(|frame| frame as f64 / 32.0).collect::<Vec<_>>();
+        assert_eq!(
+            picked_timestamps("lapse_frames", every_third_frame, &frame_times),
+            [0.0, 0.1, 0.2, 0.3]
+        );
+        let every_half_second = GifCaptureSettings {
+            mode: CaptureMode::TimeLapse {
+                every: TimeLapseInterval::Seconds(0.5),
+                fps: 10.0,
+            },
+            ..default()
+        };
+        let frame_times = (0..16).map(|frame| frame as f64 / 8.0).collect::<Vec<_>>();
+        assert_eq!(
+            picked_timestamps("lapse_seconds", every_half_second, &frame_times),
+            [0.0, 0.1, 0.2, 0.3]
+        );
+    }
+
+    fn solid_frame(width: u32, height: u32, timestamp: f64) -> CapturedFrame {
+        // Every pixel is a different shade, so frames can be told apart after resampling.
+        let data = (0..width * height)
+            .flat_map(|pixel| [pixel as u8, 0, 0, 0xFF])
+            .collect();
+        CapturedFrame {
+            data,
+            width,
+            height,
+            scale_factor: 1.0,
+            timestamp,
+        }
+    }
+
+    fn replay_timestamps(frames: &GifCaptureFrames) -> Vec<f64> {
+        frames.0.iter().map(|frame| frame.timestamp).collect()
+    }
+
+    #[test]
+    fn keeps_the_last_seconds_of_a_replay() {
+        let mut frames = GifCaptureFrames::default();
+        for timestamp in [0.0, 0.5, 1.0, 1.5, 2.0] {
+            push_replay_frame(&mut frames, solid_frame(1, 1, timestamp), 1.0);
+        }
+        assert_eq!(replay_timestamps(&frames), [1.0, 1.5, 2.0]);
+        push_replay_frame(&mut frames, solid_frame(1, 1, 4.0), 1.0);
+        assert_eq!(replay_timestamps(&frames), [4.0]);
+    }
+
+    #[test]
+    fn rejects_replay_lengths_that_arent_positive() {
+        for length in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let settings = GifCaptureSettings {
+                mode: CaptureMode::Replay { length },
+                ..default()
+            };
+            assert!(matches!(
+                settings.validate(),
+                Err(GifCaptureError::InvalidReplayLength(_))
+            ));
+        }
+        let settings = GifCaptureSettings {
+            mode: CaptureMode::Replay { length: 10.0 },
+            ..default()
+        };
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn clips_regions_to_the_source() {
+        let region = CaptureRegion {
+            x: 10,
+            y: 20,
+            width: 100,
+            height: 100,
+        };
+        assert_eq!(region.clip(200, 200), Some(region));
+        assert_eq!(
+            region.clip(50, 60),
+            Some(CaptureRegion {
+                x: 10,
+                y: 20,
+                width: 40,
+                height: 40,
+            })
+        );
+        assert_eq!(region.clip(10, 200), None);
+        let overflowing = CaptureRegion {
+            x: u32::MAX,
+            y: 0,
+            width: u32::MAX,
+            height: 1,
+        };
+        assert_eq!(overflowing.clip(200, 200), None);
+    }
+
+    #[test]
+    fn places_regions_within_viewports() {
+        let viewport = CaptureRegion {
+            x: 100,
+            y: 50,
+            width: 200,
+            height: 100,
+        };
+        let region = CaptureRegion {
+            x: 150,
+            y: 0,
+            width: 100,
+            height: 100,
+        };
+        assert_eq!(
+            region.within(viewport),
+            Some(CaptureRegion {
+                x: 250,
+                y: 50,
+                width: 50,
+                height: 100,
+            })
+        );
+        assert_eq!(CaptureRegion { x: 200, ..region }.within(viewport), None);
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn date_time(date: &str, time: &str) -> (String, String) {
+        (date.to_string(), time.to_string())
+    }
+
+    #[test]
+    fn formats_the_epoch() {
+        assert_eq!(format_date_time(at(0)), date_time("1970-01-01", "00-00-00"));
+    }
+
+    #[test]
+    fn formats_leap_days() {
+        assert_eq!(
+            format_date_time(at(1_709_164_800)),
+            date_time("2024-02-29", "00-00-00")
+        );
+        assert_eq!(
+            format_date_time(at(951_782_400 + 13 * 3600 + 37 * 60 + 5)),
+            date_time("2000-02-29", "13-37-05")
+        );
+    }
+
+    #[test]
+    fn formats_year_boundaries() {
+        assert_eq!(
+            format_date_time(at(946_684_799)),
+            date_time("1999-12-31", "23-59-59")
+        );
+        assert_eq!(
+            format_date_time(at(946_684_800)),
+            date_time("2000-01-01", "00-00-00")
+        );
+        assert_eq!(
+            format_date_time(at(1_704_067_199)),
+            date_time("2023-12-31", "23-59-59")
+        );
+    }
+
+    #[test]
+    fn expands_every_token() {
+        let tokens = [
+            ("{date}", "2024-02-29".to_string()),
+            ("{counter}", "0003".to_string()),
+        ];
+        assert_eq!(
+            expand_path(Path::new("gifs/{date}_{counter}_{date}.gif"), &tokens),
+            PathBuf::from("gifs/2024-02-29_0003_2024-02-29.gif")
+        );
+        assert_eq!(
+            expand_path(Path::new("{unknown}.gif"), &tokens),
+            PathBuf::from("{unknown}.gif")
+        );
+    }
+
+    #[test]
+    fn fills_in_the_output_path() {
+        let settings = GifCaptureSettings {
+            path: PathBuf::from("{date}_{time}_{counter}_{window}.gif"),
+            directory: PathBuf::from("gifs"),
+            ..default()
+        };
+        assert_eq!(
+            output_path(&settings, CaptureId(7), 3, at(1_709_164_800 + 61)),
+            PathBuf::from("gifs/2024-02-29_00-01-01_0003_primary.gif")
+        );
+        assert_eq!(
+            output_path(&settings, CaptureId(7), 3, at(1_709_164_800 + 62)),
+            PathBuf::from("gifs/2024-02-29_00-01-02_0003_primary.gif")
+        );
+    }
+
+    #[test]
+    fn rejects_tokens_in_directories() {
+        let settings = GifCaptureSettings {
+            path: PathBuf::from("gifs/{date}/capture.gif"),
+            ..default()
+        };
+        assert!(matches!(
+            settings.validate(),
+            Err(GifCaptureError::TokenInDirectory(_))
+        ));
+        let settings = GifCaptureSettings {
+            path: PathBuf::from("gifs/{date}.gif"),
+            ..default()
+        };
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn suffixes_before_the_extension() {
+        assert_eq!(
+            suffixed_path(Path::new("gifs/capture.gif"), "2"),
+            PathBuf::from("gifs/capture_2.gif")
+        );
+        assert_eq!(
+            suffixed_path(Path::new("capture"), "2"),
+            PathBuf::from("capture_2")
+        );
+    }
+
+    #[test]
+    fn stretches_frames() {
+        let frame = solid_frame(2, 1, 0.0);
+        assert_eq!(
+            fit_frame(&frame, 4, 2, false),
+            [0, 0, 0, 0xFF, 0, 0, 0, 0xFF, 1, 0, 0, 0xFF, 1, 0, 0, 0xFF].repeat(2)
+        );
+    }
+
+    #[test]
+    fn letterboxes_frames() {
+        let frame = solid_frame(2, 1, 0.0);
+        let black = [0u8, 0, 0, 0xFF];
+        let fitted = fit_frame(&frame, 2, 3, true);
+        assert_eq!(fitted.len(), 2 * 3 * 4);
+        assert_eq!(&fitted[..8], black.repeat(2).as_slice());
+        assert_eq!(&fitted[8..16], &frame.data[..]);
+        assert_eq!(&fitted[16..], black.repeat(2).as_slice());
     }
-    Ok(())
 }